@@ -0,0 +1,245 @@
+use chrono::{DateTime, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait, QueryFilter,
+    QuerySelect, Set, TransactionTrait,
+};
+use std::str::FromStr;
+use crate::auth::session;
+use crate::entities::{user_wallet, users};
+use crate::enums::user_type::UserType;
+
+/// Reasons an admin action on an account is refused
+#[derive(Debug)]
+pub enum AccountError {
+    NotFound,
+    /// The change would leave no enabled admin
+    LastAdmin,
+    /// Another account took the email address while this one was deleted
+    EmailTaken,
+    Db(DbErr),
+}
+
+impl From<DbErr> for AccountError {
+    fn from(err: DbErr) -> Self {
+        AccountError::Db(err)
+    }
+}
+
+fn is_active_admin(user: &users::Model) -> bool {
+    user.disabled_at.is_none() && UserType::from_str(&user.r#type) == Ok(UserType::Admin)
+}
+
+/// Lock the user row for the rest of the transaction
+async fn lock_user(txn: &DatabaseTransaction, user_id: i32) -> Result<users::Model, AccountError> {
+    users::Entity::find_active_by_id(user_id)
+        .lock_exclusive()
+        .one(txn)
+        .await?
+        .ok_or(AccountError::NotFound)
+}
+
+/// Refuse to take admin rights from the only enabled admin. The admin rows stay
+/// locked until the transaction ends, so two admins cannot demote each other at once.
+async fn ensure_not_last_admin(txn: &DatabaseTransaction, user: &users::Model) -> Result<(), AccountError> {
+    if !is_active_admin(user) {
+        return Ok(());
+    }
+
+    let admins = users::Entity::find_active()
+        .filter(users::Column::Type.eq(UserType::Admin.to_string()))
+        .filter(users::Column::DisabledAt.is_null())
+        .lock_exclusive()
+        .all(txn)
+        .await?;
+
+    if admins.iter().any(|admin| admin.id != user.id) {
+        Ok(())
+    } else {
+        Err(AccountError::LastAdmin)
+    }
+}
+
+/// Change a user's role
+pub async fn change_role(
+    db: &DatabaseConnection,
+    user_id: i32,
+    user_type: &UserType,
+) -> Result<users::Model, AccountError> {
+    let txn = db.begin().await?;
+    let user = lock_user(&txn, user_id).await?;
+
+    if *user_type != UserType::Admin {
+        ensure_not_last_admin(&txn, &user).await?;
+    }
+
+    let mut active_user: users::ActiveModel = user.into();
+    active_user.r#type = Set(user_type.to_string());
+    active_user.updated_at = Set(Utc::now());
+    let user = active_user.update(&txn).await?;
+
+    txn.commit().await?;
+    Ok(user)
+}
+
+/// Disable an account and sign it out everywhere
+pub async fn disable(db: &DatabaseConnection, user_id: i32) -> Result<users::Model, AccountError> {
+    let txn = db.begin().await?;
+    let user = lock_user(&txn, user_id).await?;
+
+    if user.disabled_at.is_some() {
+        txn.commit().await?;
+        return Ok(user);
+    }
+    ensure_not_last_admin(&txn, &user).await?;
+
+    let mut active_user: users::ActiveModel = user.into();
+    active_user.disabled_at = Set(Some(Utc::now()));
+    active_user.updated_at = Set(Utc::now());
+    let user = active_user.update(&txn).await?;
+
+    txn.commit().await?;
+
+    session::revoke_all_for_user(db, user.id, None).await?;
+    Ok(user)
+}
+
+/// Let a disabled account sign in again
+pub async fn enable(db: &DatabaseConnection, user_id: i32) -> Result<users::Model, AccountError> {
+    let user = users::Entity::find_active_by_id(user_id).one(db).await?.ok_or(AccountError::NotFound)?;
+    if user.disabled_at.is_none() {
+        return Ok(user);
+    }
+
+    let mut active_user: users::ActiveModel = user.into();
+    active_user.disabled_at = Set(None);
+    active_user.updated_at = Set(Utc::now());
+    Ok(active_user.update(db).await?)
+}
+
+/// Soft delete an account together with its wallets and sign it out everywhere
+pub async fn delete(db: &DatabaseConnection, user_id: i32) -> Result<(), AccountError> {
+    let txn = db.begin().await?;
+    let user = lock_user(&txn, user_id).await?;
+    ensure_not_last_admin(&txn, &user).await?;
+
+    let now = Utc::now();
+    let mut active_user: users::ActiveModel = user.into();
+    active_user.deleted_at = Set(Some(now));
+    active_user.updated_at = Set(now);
+    active_user.update(&txn).await?;
+
+    user_wallet::Entity::update_many()
+        .col_expr(user_wallet::Column::DeletedAt, Expr::value(now))
+        .col_expr(user_wallet::Column::UpdatedAt, Expr::value(now))
+        .filter(user_wallet::Column::UserId.eq(user_id as i64))
+        .filter(user_wallet::Column::DeletedAt.is_null())
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    session::revoke_all_for_user(db, user_id, None).await?;
+    Ok(())
+}
+
+/// Bring back a soft deleted account and the wallets deleted with it
+pub async fn restore(db: &DatabaseConnection, user_id: i32) -> Result<users::Model, AccountError> {
+    let txn = db.begin().await?;
+    let user = users::Entity::find_by_id(user_id)
+        .filter(users::Column::DeletedAt.is_not_null())
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or(AccountError::NotFound)?;
+
+    let email_taken = users::Entity::find_active()
+        .filter(users::Column::Email.eq(user.email.clone()))
+        .one(&txn)
+        .await?
+        .is_some();
+    if email_taken {
+        return Err(AccountError::EmailTaken);
+    }
+
+    let now = Utc::now();
+    let deleted_at = user.deleted_at;
+    let mut active_user: users::ActiveModel = user.into();
+    active_user.deleted_at = Set(None);
+    active_user.updated_at = Set(now);
+    let user = active_user.update(&txn).await?;
+
+    // `delete` stamps the account and its wallets with the same time; wallets removed
+    // on their own before that stay deleted
+    user_wallet::Entity::update_many()
+        .col_expr(user_wallet::Column::DeletedAt, Expr::value(Option::<DateTime<Utc>>::None))
+        .col_expr(user_wallet::Column::UpdatedAt, Expr::value(now))
+        .filter(user_wallet::Column::UserId.eq(user_id as i64))
+        .filter(user_wallet::Column::DeletedAt.eq(deleted_at))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use futures::join;
+    use sea_orm::{EntityTrait, Set};
+    use super::AccountError;
+    use crate::entities::user_wallet;
+    use crate::enums::user_type::UserType;
+    use crate::test_support;
+
+    async fn add_wallet(db: &sea_orm::DatabaseConnection, user_id: i32, address: &str, deleted: bool) -> i32 {
+        user_wallet::Entity::insert(user_wallet::ActiveModel {
+            user_id: Set(user_id as i64),
+            encrypted_mnemonic: Set(String::new()),
+            encrypted_private_key: Set(String::new()),
+            public_key: Set(address.to_string()),
+            address: Set(address.to_string()),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+            deleted_at: Set(deleted.then(|| Utc::now() - Duration::days(30))),
+            ..Default::default()
+        })
+        .exec(db)
+        .await
+        .unwrap()
+        .last_insert_id
+    }
+
+    #[actix_web::test]
+    async fn restore_keeps_wallets_deleted_before_the_account() {
+        let db = test_support::database().await;
+        let user = test_support::create_user(&db, UserType::Member, "restore@example.com").await;
+        let live_wallet = add_wallet(&db, user.id, "live", false).await;
+        let retired_wallet = add_wallet(&db, user.id, "retired", true).await;
+
+        super::delete(&db, user.id).await.unwrap();
+        let restored = super::restore(&db, user.id).await.unwrap();
+        assert!(restored.deleted_at.is_none());
+
+        let wallet = |id| user_wallet::Entity::find_by_id(id).one(&db);
+        assert!(wallet(live_wallet).await.unwrap().unwrap().deleted_at.is_none());
+        assert!(wallet(retired_wallet).await.unwrap().unwrap().deleted_at.is_some());
+    }
+
+    #[actix_web::test]
+    async fn the_last_enabled_admin_cannot_be_removed() {
+        let db = test_support::database().await;
+        let admin = test_support::create_user(&db, UserType::Admin, "only-admin@example.com").await;
+
+        assert!(matches!(super::delete(&db, admin.id).await, Err(AccountError::LastAdmin)));
+        assert!(matches!(super::disable(&db, admin.id).await, Err(AccountError::LastAdmin)));
+        assert!(matches!(super::change_role(&db, admin.id, &UserType::Member).await, Err(AccountError::LastAdmin)));
+
+        // With a second admin either one can go, but not both at once
+        let other = test_support::create_user(&db, UserType::Admin, "other-admin@example.com").await;
+        let (first, second) = join!(super::disable(&db, admin.id), super::disable(&db, other.id));
+        assert!(first.is_ok() != second.is_ok());
+        assert!(matches!(if first.is_ok() { second } else { first }, Err(AccountError::LastAdmin)));
+    }
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use crate::auth::token::{generate_token, hash_token};
+use crate::entities::api_keys;
+
+/// Marks a bearer token as an API key rather than a JWT
+pub const KEY_PREFIX: &str = "pdk_";
+/// How many characters of the key are kept in clear text for display
+const DISPLAY_PREFIX_LENGTH: usize = 12;
+/// `last_used_at` is only written again once it is older than this
+const LAST_USED_RESOLUTION_SECONDS: i64 = 60;
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(KEY_PREFIX)
+}
+
+/// Scopes look like `resource:action`, e.g. `users:read`
+pub fn is_valid_scope(scope: &str) -> bool {
+    let valid_part = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c == '_');
+    match scope.split_once(':') {
+        Some((resource, action)) => valid_part(resource) && valid_part(action),
+        None => false,
+    }
+}
+
+/// Create a key and return it together with the plain key, which is never stored
+pub async fn create(
+    db: &DatabaseConnection,
+    name: &str,
+    scopes: &[String],
+    expires_at: Option<DateTime<Utc>>,
+    created_by: i32,
+) -> Result<(api_keys::Model, String), DbErr> {
+    let key = format!("{}{}", KEY_PREFIX, generate_token());
+    let api_key = api_keys::ActiveModel {
+        name: Set(name.to_string()),
+        key_prefix: Set(key[..DISPLAY_PREFIX_LENGTH].to_string()),
+        key_hash: Set(hash_token(&key)),
+        scopes: Set(scopes.join(",")),
+        created_by: Set(created_by),
+        expires_at: Set(expires_at),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    Ok((api_key.insert(db).await?, key))
+}
+
+/// Look up an active key and record that it was used
+pub async fn authenticate(db: &DatabaseConnection, key: &str) -> Result<Option<api_keys::Model>, DbErr> {
+    let api_key = api_keys::Entity::find()
+        .filter(api_keys::Column::KeyHash.eq(hash_token(key)))
+        .filter(api_keys::Column::RevokedAt.is_null())
+        .one(db)
+        .await?;
+
+    let api_key = match api_key {
+        Some(api_key) if api_key.expires_at.is_none_or(|expires_at| expires_at > Utc::now()) => api_key,
+        _ => return Ok(None),
+    };
+
+    // Busy integrations would otherwise write to the row on every request
+    let now = Utc::now();
+    api_keys::Entity::update_many()
+        .col_expr(api_keys::Column::LastUsedAt, Expr::value(now))
+        .filter(api_keys::Column::Id.eq(api_key.id))
+        .filter(
+            Condition::any()
+                .add(api_keys::Column::LastUsedAt.is_null())
+                .add(api_keys::Column::LastUsedAt.lt(now - Duration::seconds(LAST_USED_RESOLUTION_SECONDS))),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(Some(api_key))
+}
+
+/// Revoke a key; returns false when there was no active key with this id
+pub async fn revoke(db: &DatabaseConnection, id: i32) -> Result<bool, DbErr> {
+    let result = api_keys::Entity::update_many()
+        .col_expr(api_keys::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(api_keys::Column::Id.eq(id))
+        .filter(api_keys::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
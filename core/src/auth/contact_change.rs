@@ -0,0 +1,182 @@
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use jsonwebtoken::Validation;
+use rand::rngs::OsRng;
+use rand::Rng;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use serde::{Deserialize, Serialize};
+use std::env;
+use crate::auth::purpose_token;
+use crate::auth::rate_limit::RateLimit;
+use crate::auth::token::hash_token;
+use crate::entities::{phone_changes, users};
+use crate::mail::mailer::Mailer;
+use crate::sms::sender::SmsSender;
+
+const EMAIL_CHANGE_PURPOSE: &str = "email_change";
+const EMAIL_CHANGE_TTL_HOURS: i64 = 24;
+const PHONE_CODE_TTL_MINUTES: i64 = 10;
+/// Wrong codes allowed before the pending change is thrown away
+const MAX_PHONE_CODE_ATTEMPTS: i32 = 5;
+
+lazy_static! {
+    static ref EMAIL_CHANGES_PER_USER: RateLimit =
+        RateLimit::from_env("EMAIL_CHANGE_MAX_PER_USER", 3, Duration::hours(1));
+    static ref PHONE_CHANGES_PER_USER: RateLimit =
+        RateLimit::from_env("PHONE_CHANGE_MAX_PER_USER", 3, Duration::hours(1));
+}
+
+/// Reasons a pending email or phone change cannot be confirmed
+#[derive(Debug)]
+pub enum ContactChangeError {
+    /// The link or code is unknown, expired or no longer matches the account
+    Invalid,
+    /// The phone code was wrong
+    WrongCode,
+    /// Too many wrong codes; the change has to be requested again
+    TooManyAttempts,
+    Db(DbErr),
+}
+
+impl From<DbErr> for ContactChangeError {
+    fn from(err: DbErr) -> Self {
+        ContactChangeError::Db(err)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EmailChangeClaims {
+    sub: String,
+    /// The address the account had when the change was requested
+    current_email: String,
+    new_email: String,
+    purpose: String,
+    exp: usize,
+}
+
+/// Count an email change request against the user, or return the seconds until another is allowed
+pub fn throttle_email_change(user_id: i32) -> Option<i64> {
+    EMAIL_CHANGES_PER_USER.hit(&user_id.to_string())
+}
+
+/// Count a phone change request against the user, or return the seconds until another is allowed.
+/// Every request sends an SMS, so this also caps what one account can cost.
+pub fn throttle_phone_change(user_id: i32) -> Option<i64> {
+    PHONE_CHANGES_PER_USER.hit(&user_id.to_string())
+}
+
+/// Email a confirmation link to the new address. The account keeps its current
+/// address until the link is opened.
+pub async fn request_email_change(user: &users::Model, new_email: &str) -> Result<(), String> {
+    let claims = EmailChangeClaims {
+        sub: user.id.to_string(),
+        current_email: user.email.clone(),
+        new_email: new_email.to_string(),
+        purpose: EMAIL_CHANGE_PURPOSE.to_string(),
+        exp: (Utc::now() + Duration::hours(EMAIL_CHANGE_TTL_HOURS)).timestamp() as usize,
+    };
+    let token = purpose_token::sign(&claims)
+        .map_err(|e| format!("Failed to sign email change token: {}", e))?;
+
+    let frontend_url = env::var("FRONTEND_URL").expect("FRONTEND_URL must be set");
+    let link = format!("{}/confirm-email-change?token={}", frontend_url.trim_end_matches('/'), token);
+    let body = format!(
+        "Hi {},\n\nPlease confirm that you want to use this address for your proctodot account by opening the link below:\n\n{}\n\nThe link expires in {} hours.",
+        user.first_name, link, EMAIL_CHANGE_TTL_HOURS
+    );
+
+    Mailer::send(new_email, "Confirm your new proctodot email address", body).await
+}
+
+/// Validate an email change link and return the user id, the address the
+/// account had when the change was requested, and the new address
+pub fn verify_email_change(token: &str) -> Result<(i32, String, String), ContactChangeError> {
+    let claims = purpose_token::decode::<EmailChangeClaims>(token, &Validation::default())
+        .map_err(|_| ContactChangeError::Invalid)?;
+
+    if claims.purpose != EMAIL_CHANGE_PURPOSE {
+        return Err(ContactChangeError::Invalid);
+    }
+
+    let user_id = claims.sub.parse::<i32>().map_err(|_| ContactChangeError::Invalid)?;
+    Ok((user_id, claims.current_email, claims.new_email))
+}
+
+/// Tell the old address that the account has moved, in case the change was not the owner's doing
+pub async fn notify_email_changed(user: &users::Model, old_email: &str) -> Result<(), String> {
+    let body = format!(
+        "Hi {},\n\nThe email address of your proctodot account was changed to {}. If you did not make this change, please contact support immediately.",
+        user.first_name, user.email
+    );
+
+    Mailer::send(old_email, "Your proctodot email address was changed", body).await
+}
+
+/// Start a phone change, replacing any pending one, and text a code to the new number
+pub async fn request_phone_change(db: &DatabaseConnection, user_id: i32, phone: &str) -> Result<(), ContactChangeError> {
+    phone_changes::Entity::delete_many()
+        .filter(phone_changes::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    let code = format!("{:06}", OsRng.gen_range(0..1_000_000));
+    let pending = phone_changes::ActiveModel {
+        user_id: Set(user_id),
+        phone: Set(phone.to_string()),
+        code_hash: Set(hash_token(&code)),
+        attempts: Set(0),
+        expires_at: Set(Utc::now() + Duration::minutes(PHONE_CODE_TTL_MINUTES)),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+    phone_changes::Entity::insert(pending).exec(db).await?;
+
+    let message = format!(
+        "Your proctodot verification code is {}. It expires in {} minutes.",
+        code, PHONE_CODE_TTL_MINUTES
+    );
+    if let Err(e) = SmsSender::send(phone, message).await {
+        log::error!("Error sending phone verification code to user {}: {}", user_id, e);
+    }
+
+    Ok(())
+}
+
+/// Check the code for the user's pending phone change and return the confirmed number
+pub async fn confirm_phone_change(db: &DatabaseConnection, user_id: i32, code: &str) -> Result<String, ContactChangeError> {
+    let pending = phone_changes::Entity::find()
+        .filter(phone_changes::Column::UserId.eq(user_id))
+        .one(db)
+        .await?
+        .ok_or(ContactChangeError::Invalid)?;
+
+    if pending.expires_at <= Utc::now() {
+        phone_changes::Entity::delete_by_id(pending.id).exec(db).await?;
+        return Err(ContactChangeError::Invalid);
+    }
+
+    if pending.code_hash != hash_token(code.trim()) {
+        // Count the miss atomically so parallel guesses cannot exceed the limit
+        let result = phone_changes::Entity::update_many()
+            .col_expr(phone_changes::Column::Attempts, Expr::col(phone_changes::Column::Attempts).add(1))
+            .filter(phone_changes::Column::Id.eq(pending.id))
+            .filter(phone_changes::Column::Attempts.lt(MAX_PHONE_CODE_ATTEMPTS - 1))
+            .exec(db)
+            .await?;
+
+        if result.rows_affected == 0 {
+            phone_changes::Entity::delete_by_id(pending.id).exec(db).await?;
+            return Err(ContactChangeError::TooManyAttempts);
+        }
+        return Err(ContactChangeError::WrongCode);
+    }
+
+    // Only one confirmation may win
+    let result = phone_changes::Entity::delete_by_id(pending.id).exec(db).await?;
+    if result.rows_affected == 0 {
+        return Err(ContactChangeError::Invalid);
+    }
+
+    Ok(pending.phone)
+}
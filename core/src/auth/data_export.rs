@@ -0,0 +1,274 @@
+use actix_web::web;
+use chrono::{Duration, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, Set};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use crate::entities::{
+    chat_room_members, data_exports, impersonation_logs, lockout_events, passkey_credentials,
+    sessions, user_identities, user_wallet, users,
+};
+
+const DEFAULT_DATA_EXPORT_DIR: &str = "./storage/exports";
+const DEFAULT_DATA_EXPORT_TTL_HOURS: i64 = 72;
+
+const STATUS_PENDING: &str = "pending";
+const STATUS_RUNNING: &str = "running";
+const STATUS_COMPLETED: &str = "completed";
+const STATUS_FAILED: &str = "failed";
+
+/// A job still pending or running after this long was lost, e.g. to a restart, and no longer blocks new requests
+const STALE_AFTER_MINUTES: i64 = 60;
+
+#[derive(Debug)]
+pub enum ExportError {
+    /// An export for the user is already being built
+    InProgress(data_exports::Model),
+    Db(DbErr),
+}
+
+impl From<DbErr> for ExportError {
+    fn from(err: DbErr) -> Self {
+        ExportError::Db(err)
+    }
+}
+
+/// Directory archives are written to, configurable through DATA_EXPORT_DIR
+fn export_dir() -> PathBuf {
+    PathBuf::from(env::var("DATA_EXPORT_DIR").unwrap_or_else(|_| DEFAULT_DATA_EXPORT_DIR.to_string()))
+}
+
+/// How long a finished archive stays downloadable, configurable through DATA_EXPORT_TTL_HOURS
+pub fn export_ttl() -> Duration {
+    let hours = env::var("DATA_EXPORT_TTL_HOURS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_DATA_EXPORT_TTL_HOURS);
+    Duration::hours(hours)
+}
+
+/// Queue an export of the user's data and build it in the background
+pub async fn request(
+    db: &DatabaseConnection,
+    user_id: i32,
+    requested_by: i32,
+) -> Result<data_exports::Model, ExportError> {
+    purge_expired(db).await?;
+
+    let in_progress = data_exports::Entity::find()
+        .filter(data_exports::Column::UserId.eq(user_id))
+        .filter(data_exports::Column::Status.is_in([STATUS_PENDING, STATUS_RUNNING]))
+        .filter(data_exports::Column::CreatedAt.gt(Utc::now() - Duration::minutes(STALE_AFTER_MINUTES)))
+        .one(db)
+        .await?;
+    if let Some(export) = in_progress {
+        return Err(ExportError::InProgress(export));
+    }
+
+    let export = data_exports::ActiveModel {
+        user_id: Set(user_id),
+        requested_by: Set(requested_by),
+        status: Set(STATUS_PENDING.to_string()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    let db = db.clone();
+    let export_id = export.id;
+    actix_web::rt::spawn(async move {
+        if let Err(e) = run(&db, export_id).await {
+            log::error!("Error recording the outcome of data export {}: {}", export_id, e);
+        }
+    });
+
+    Ok(export)
+}
+
+/// Exports of the user, newest first
+pub async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<data_exports::Model>, DbErr> {
+    data_exports::Entity::find()
+        .filter(data_exports::Column::UserId.eq(user_id))
+        .order_by_desc(data_exports::Column::Id)
+        .all(db)
+        .await
+}
+
+/// Read a completed, unexpired archive; `None` when there is nothing to download
+pub async fn read_archive(export: &data_exports::Model) -> Option<Vec<u8>> {
+    if export.status != STATUS_COMPLETED || export.expires_at.is_none_or(|at| at <= Utc::now()) {
+        return None;
+    }
+
+    let path = export_dir().join(export.file_name.as_ref()?);
+    match web::block(move || fs::read(path)).await {
+        Ok(Ok(bytes)) => Some(bytes),
+        Ok(Err(e)) => {
+            log::error!("Error reading data export {}: {}", export.id, e);
+            None
+        }
+        Err(e) => {
+            log::error!("Data export read task failed: {}", e);
+            None
+        }
+    }
+}
+
+async fn run(db: &DatabaseConnection, export_id: i32) -> Result<(), DbErr> {
+    let export = match data_exports::Entity::find_by_id(export_id).one(db).await? {
+        Some(export) => export,
+        None => return Ok(()),
+    };
+    let user_id = export.user_id;
+
+    let mut export: data_exports::ActiveModel = export.into();
+    export.status = Set(STATUS_RUNNING.to_string());
+    let export = export.update(db).await?;
+
+    let file_name = format!("export-{}-{}.json", user_id, export_id);
+    let outcome = match build(db, user_id).await {
+        Ok(archive) => write_archive(file_name.clone(), archive).await,
+        Err(e) => Err(format!("Error collecting data: {}", e)),
+    };
+
+    let mut export: data_exports::ActiveModel = export.into();
+    match outcome {
+        Ok(()) => {
+            export.status = Set(STATUS_COMPLETED.to_string());
+            export.file_name = Set(Some(file_name));
+            export.completed_at = Set(Some(Utc::now()));
+            export.expires_at = Set(Some(Utc::now() + export_ttl()));
+        }
+        Err(e) => {
+            log::error!("Data export {} failed: {}", export_id, e);
+            export.status = Set(STATUS_FAILED.to_string());
+            export.error = Set(Some("The export could not be built.".to_string()));
+            export.completed_at = Set(Some(Utc::now()));
+        }
+    }
+    export.update(db).await?;
+
+    Ok(())
+}
+
+/// Collect everything held about the user into one machine-readable document
+async fn build(db: &DatabaseConnection, user_id: i32) -> Result<serde_json::Value, DbErr> {
+    // Not find_active: an admin may export the data of a soft deleted account
+    let user = users::Entity::find_by_id(user_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| DbErr::RecordNotFound(format!("User {} not found", user_id)))?;
+
+    let mut profile = serde_json::json!(user);
+    profile["created_at"] = serde_json::json!(user.created_at);
+    profile["updated_at"] = serde_json::json!(user.updated_at);
+    profile["deleted_at"] = serde_json::json!(user.deleted_at);
+
+    // Only the public side of each wallet; the mnemonic and private key never leave the database
+    let wallets: Vec<serde_json::Value> = user_wallet::Entity::find()
+        .filter(user_wallet::Column::UserId.eq(user_id as i64))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|wallet| serde_json::json!({
+            "address": wallet.address,
+            "public_key": wallet.public_key,
+            "created_at": wallet.created_at,
+            "deleted_at": wallet.deleted_at,
+        }))
+        .collect();
+
+    let chat_rooms = chat_room_members::Entity::find()
+        .filter(chat_room_members::Column::UserId.eq(user_id))
+        .order_by_asc(chat_room_members::Column::FirstJoinedAt)
+        .all(db)
+        .await?;
+
+    let sessions = sessions::Entity::find()
+        .filter(sessions::Column::UserId.eq(user_id))
+        .order_by_asc(sessions::Column::Id)
+        .all(db)
+        .await?;
+
+    let identities: Vec<serde_json::Value> = user_identities::Entity::find()
+        .filter(user_identities::Column::UserId.eq(user_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|identity| {
+            let mut value = serde_json::json!(identity);
+            value["created_at"] = serde_json::json!(identity.created_at);
+            value
+        })
+        .collect();
+
+    let passkeys = passkey_credentials::Entity::find()
+        .filter(passkey_credentials::Column::UserId.eq(user_id))
+        .all(db)
+        .await?;
+
+    let lockouts = lockout_events::Entity::find()
+        .filter(lockout_events::Column::UserId.eq(user_id))
+        .order_by_asc(lockout_events::Column::Id)
+        .all(db)
+        .await?;
+
+    let impersonations = impersonation_logs::Entity::find()
+        .filter(impersonation_logs::Column::UserId.eq(user_id))
+        .order_by_asc(impersonation_logs::Column::Id)
+        .all(db)
+        .await?;
+
+    Ok(serde_json::json!({
+        "generated_at": Utc::now(),
+        "user": profile,
+        "wallets": wallets,
+        "chat_rooms": chat_rooms,
+        "events": {
+            "sessions": sessions,
+            "linked_identities": identities,
+            "passkeys": passkeys,
+            "login_lockouts": lockouts,
+            "impersonations": impersonations,
+        },
+    }))
+}
+
+async fn write_archive(file_name: String, archive: serde_json::Value) -> Result<(), String> {
+    let dir = export_dir();
+    web::block(move || {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create export directory: {}", e))?;
+        let bytes = serde_json::to_vec_pretty(&archive).map_err(|e| format!("Failed to serialize export: {}", e))?;
+        fs::write(dir.join(file_name), bytes).map_err(|e| format!("Failed to write export: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Export write task failed: {}", e))?
+}
+
+/// Delete archives past their expiry so personal data does not linger on disk
+async fn purge_expired(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let expired = data_exports::Entity::find()
+        .filter(data_exports::Column::ExpiresAt.lte(Utc::now()))
+        .filter(data_exports::Column::FileName.is_not_null())
+        .all(db)
+        .await?;
+
+    for export in expired {
+        if let Some(file_name) = export.file_name.clone() {
+            let path = export_dir().join(file_name);
+            if let Ok(Err(e)) = web::block(move || fs::remove_file(path)).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Error deleting expired data export {}: {}", export.id, e);
+                    continue;
+                }
+            }
+        }
+
+        let mut export: data_exports::ActiveModel = export.into();
+        export.file_name = Set(None);
+        export.update(db).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use chrono::{Duration, Utc};
+use jsonwebtoken::Validation;
+use serde::{Deserialize, Serialize};
+use std::env;
+use crate::auth::purpose_token;
+use crate::entities::users;
+use crate::mail::mailer::Mailer;
+
+const PURPOSE: &str = "email_verification";
+const LINK_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VerificationClaims {
+    sub: String,
+    email: String,
+    purpose: String,
+    exp: usize,
+}
+
+/// Whether AuthMiddleware should reject users who have not verified their email
+pub fn verification_required() -> bool {
+    env::var("REQUIRE_EMAIL_VERIFICATION")
+        .map(|value| value == "true" || value == "1")
+        .unwrap_or(false)
+}
+
+/// Create a signed token bound to the user and the address being verified
+pub fn create_token(user: &users::Model) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = VerificationClaims {
+        sub: user.id.to_string(),
+        email: user.email.clone(),
+        purpose: PURPOSE.to_string(),
+        exp: (Utc::now() + Duration::hours(LINK_TTL_HOURS)).timestamp() as usize,
+    };
+
+    purpose_token::sign(&claims)
+}
+
+/// Validate a verification token and return the user id and email it was issued for
+pub fn verify_token(token: &str) -> Result<(i32, String), &'static str> {
+    let claims = purpose_token::decode::<VerificationClaims>(token, &Validation::default())
+        .map_err(|_| "Invalid or expired verification link")?;
+
+    if claims.purpose != PURPOSE {
+        return Err("Invalid or expired verification link");
+    }
+
+    let user_id = claims.sub.parse::<i32>().map_err(|_| "Invalid or expired verification link")?;
+    Ok((user_id, claims.email))
+}
+
+/// Email a verification link pointing at the frontend
+pub async fn send_verification_email(user: &users::Model) -> Result<(), String> {
+    let token = create_token(user).map_err(|e| format!("Failed to sign verification token: {}", e))?;
+    let frontend_url = env::var("FRONTEND_URL").expect("FRONTEND_URL must be set");
+    let link = format!("{}/verify-email?token={}", frontend_url.trim_end_matches('/'), token);
+
+    let body = format!(
+        "Hi {},\n\nPlease confirm your email address by opening the link below:\n\n{}\n\nThe link expires in {} hours.",
+        user.first_name, link, LINK_TTL_HOURS
+    );
+
+    Mailer::send(&user.email, "Verify your proctodot email address", body).await
+}
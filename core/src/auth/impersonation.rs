@@ -0,0 +1,44 @@
+use chrono::Utc;
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, Set};
+use std::str::FromStr;
+use crate::entities::{impersonation_logs, users};
+use crate::enums::user_type::UserType;
+use crate::http::middlewares::auth::Actor;
+
+/// Resolve the admin named by an `act` claim, as long as they are still an enabled admin
+pub async fn active_impersonator(db: &DatabaseConnection, actor: &Actor) -> Result<Option<i32>, DbErr> {
+    let admin_id = match actor.sub.parse::<i32>() {
+        Ok(admin_id) => admin_id,
+        Err(_) => return Ok(None),
+    };
+
+    let admin = users::Entity::find_active_by_id(admin_id).one(db).await?;
+    Ok(admin
+        .filter(|admin| admin.disabled_at.is_none() && UserType::from_str(&admin.r#type) == Ok(UserType::Admin))
+        .map(|admin| admin.id))
+}
+
+/// Append an entry to the impersonation audit trail
+pub async fn record(
+    db: &DatabaseConnection,
+    admin_id: i32,
+    user_id: i32,
+    token_id: &str,
+    method: &str,
+    path: &str,
+    status_code: u16,
+) -> Result<(), DbErr> {
+    let entry = impersonation_logs::ActiveModel {
+        admin_id: Set(admin_id),
+        user_id: Set(user_id),
+        token_id: Set(token_id.to_string()),
+        method: Set(method.to_string()),
+        path: Set(path.to_string()),
+        status_code: Set(status_code as i32),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    impersonation_logs::Entity::insert(entry).exec(db).await?;
+    Ok(())
+}
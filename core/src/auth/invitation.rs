@@ -0,0 +1,149 @@
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use std::env;
+use crate::auth::token::{generate_token, hash_token};
+use crate::entities::invitations;
+use crate::enums::user_type::UserType;
+use crate::mail::mailer::Mailer;
+
+const DEFAULT_INVITATION_TTL_DAYS: i64 = 7;
+
+/// Reasons an invite code cannot be redeemed
+#[derive(Debug)]
+pub enum InvitationError {
+    /// The code is unknown, already used, expired or issued for another role
+    Invalid,
+    /// The invite is bound to a different email address
+    EmailMismatch,
+    Db(DbErr),
+}
+
+impl From<DbErr> for InvitationError {
+    fn from(err: DbErr) -> Self {
+        InvitationError::Db(err)
+    }
+}
+
+/// Lifetime of an invite, configurable through INVITATION_TTL_DAYS
+pub fn invitation_ttl() -> Duration {
+    let days = env::var("INVITATION_TTL_DAYS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_INVITATION_TTL_DAYS);
+    Duration::days(days)
+}
+
+/// Whether registering as this user type needs an invite; only members can sign up on their own
+pub fn required_for(user_type: &UserType) -> bool {
+    *user_type != UserType::Member
+}
+
+/// Create an invite for the role and return it together with the plain code
+pub async fn create(
+    db: &DatabaseConnection,
+    role: &UserType,
+    email: Option<String>,
+    created_by: i32,
+) -> Result<(invitations::Model, String), DbErr> {
+    let code = generate_token();
+    let invitation = invitations::ActiveModel {
+        code_hash: Set(hash_token(&code)),
+        role: Set(role.to_string()),
+        email: Set(email.map(|email| email.trim().to_lowercase())),
+        created_by: Set(created_by),
+        expires_at: Set(Utc::now() + invitation_ttl()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    Ok((invitation.insert(db).await?, code))
+}
+
+/// Reserve an invite for a registration. The invite is burnt straight away so two
+/// registrations cannot race for it; call `release` if the registration then fails.
+pub async fn claim(
+    db: &DatabaseConnection,
+    code: &str,
+    role: &UserType,
+    email: &str,
+) -> Result<invitations::Model, InvitationError> {
+    let invitation = invitations::Entity::find()
+        .filter(invitations::Column::CodeHash.eq(hash_token(code)))
+        .one(db)
+        .await?
+        .ok_or(InvitationError::Invalid)?;
+
+    if invitation.used_at.is_some()
+        || invitation.expires_at <= Utc::now()
+        || invitation.role != role.to_string()
+    {
+        return Err(InvitationError::Invalid);
+    }
+
+    if let Some(invited_email) = &invitation.email {
+        if *invited_email != email.trim().to_lowercase() {
+            return Err(InvitationError::EmailMismatch);
+        }
+    }
+
+    let result = invitations::Entity::update_many()
+        .col_expr(invitations::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(invitations::Column::Id.eq(invitation.id))
+        .filter(invitations::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(InvitationError::Invalid);
+    }
+
+    Ok(invitation)
+}
+
+/// Record which user redeemed a claimed invite
+pub async fn complete(db: &DatabaseConnection, invitation_id: i32, user_id: i32) -> Result<(), DbErr> {
+    invitations::Entity::update_many()
+        .col_expr(invitations::Column::UsedBy, Expr::value(user_id))
+        .filter(invitations::Column::Id.eq(invitation_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Make a claimed invite usable again after the registration it was claimed for failed
+pub async fn release(db: &DatabaseConnection, invitation_id: i32) -> Result<(), DbErr> {
+    invitations::Entity::update_many()
+        .col_expr(invitations::Column::UsedAt, Expr::value(Option::<chrono::DateTime<Utc>>::None))
+        .filter(invitations::Column::Id.eq(invitation_id))
+        .filter(invitations::Column::UsedBy.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Email the invite link to the address the invite is bound to
+pub async fn send_invitation_email(invitation: &invitations::Model, code: &str) -> Result<(), String> {
+    let email = match &invitation.email {
+        Some(email) => email,
+        None => return Ok(()),
+    };
+
+    let frontend_url = env::var("FRONTEND_URL").expect("FRONTEND_URL must be set");
+    let link = format!(
+        "{}/register/{}?invite={}",
+        frontend_url.trim_end_matches('/'),
+        invitation.role,
+        code
+    );
+
+    let body = format!(
+        "Hi,\n\nYou have been invited to join proctodot as {} {}. Open the link below to create your account:\n\n{}\n\nThe invite expires in {} days and can only be used once.",
+        if invitation.role == UserType::Admin.to_string() { "an" } else { "a" },
+        invitation.role,
+        link,
+        invitation_ttl().num_days()
+    );
+
+    Mailer::send(email, "You're invited to proctodot", body).await
+}
@@ -0,0 +1,288 @@
+use actix_web::HttpRequest;
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use std::env;
+use crate::entities::{lockout_events, login_attempts};
+
+const KEY_EMAIL: &str = "email";
+const KEY_IP: &str = "ip";
+
+const DEFAULT_MAX_ATTEMPTS_PER_EMAIL: i32 = 5;
+const DEFAULT_MAX_ATTEMPTS_PER_IP: i32 = 20;
+const DEFAULT_LOCKOUT_MINUTES: i64 = 15;
+/// Failures older than this no longer count towards a lockout
+const ATTEMPT_WINDOW_MINUTES: i64 = 15;
+/// Failures allowed before every further attempt has to wait
+const BACKOFF_AFTER_ATTEMPTS: i32 = 3;
+const MAX_BACKOFF_SECONDS: i64 = 300;
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn max_attempts(key_type: &str) -> i32 {
+    match key_type {
+        KEY_IP => env_or("LOGIN_MAX_ATTEMPTS_PER_IP", DEFAULT_MAX_ATTEMPTS_PER_IP),
+        _ => env_or("LOGIN_MAX_ATTEMPTS_PER_EMAIL", DEFAULT_MAX_ATTEMPTS_PER_EMAIL),
+    }
+}
+
+fn lockout_duration() -> Duration {
+    Duration::minutes(env_or("LOGIN_LOCKOUT_MINUTES", DEFAULT_LOCKOUT_MINUTES))
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Address of the client; forwarding headers are only honoured when TRUST_PROXY_HEADERS is set
+pub fn client_ip(req: &HttpRequest) -> String {
+    let trust_proxy = env::var("TRUST_PROXY_HEADERS").map(|value| value == "true").unwrap_or(false);
+    let ip = if trust_proxy {
+        req.connection_info().realip_remote_addr().map(|addr| addr.to_string())
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    };
+    ip.unwrap_or_else(|| "unknown".to_string())
+}
+
+async fn find_counter(
+    db: &DatabaseConnection,
+    key_type: &str,
+    key_value: &str,
+) -> Result<Option<login_attempts::Model>, DbErr> {
+    login_attempts::Entity::find()
+        .filter(login_attempts::Column::KeyType.eq(key_type))
+        .filter(login_attempts::Column::KeyValue.eq(key_value))
+        .one(db)
+        .await
+}
+
+/// When the counter next allows an attempt: the lockout end, or the exponential backoff delay
+fn blocked_until(counter: &login_attempts::Model) -> Option<DateTime<Utc>> {
+    if let Some(locked_until) = counter.locked_until {
+        if locked_until > Utc::now() {
+            return Some(locked_until);
+        }
+    }
+
+    if counter.failed_count >= BACKOFF_AFTER_ATTEMPTS {
+        let exponent = (counter.failed_count - BACKOFF_AFTER_ATTEMPTS).min(16) as u32;
+        let delay = 2i64.pow(exponent).min(MAX_BACKOFF_SECONDS);
+        let next_attempt = counter.last_failed_at + Duration::seconds(delay);
+        if next_attempt > Utc::now() {
+            return Some(next_attempt);
+        }
+    }
+
+    None
+}
+
+/// Return how many seconds the client has to wait before it may try to log in again, if at all
+pub async fn check(db: &DatabaseConnection, email: &str, ip: &str) -> Result<Option<i64>, DbErr> {
+    let mut retry_at: Option<DateTime<Utc>> = None;
+    for (key_type, key_value) in [(KEY_EMAIL, normalize_email(email)), (KEY_IP, ip.to_string())] {
+        if let Some(until) = find_counter(db, key_type, &key_value).await?.as_ref().and_then(blocked_until) {
+            retry_at = Some(retry_at.map_or(until, |current| current.max(until)));
+        }
+    }
+
+    Ok(retry_at.map(|until| (until - Utc::now()).num_seconds().max(1)))
+}
+
+/// Add one failure to a counter in SQL, so concurrent failures cannot overwrite each other's
+/// increments. Counters past the attempt window start over unless they are locked.
+async fn increment(db: &DatabaseConnection, key_type: &str, key_value: &str) -> Result<(), DbErr> {
+    let now = Utc::now();
+    login_attempts::Entity::update_many()
+        .col_expr(login_attempts::Column::FailedCount, Expr::value(0))
+        .filter(login_attempts::Column::KeyType.eq(key_type))
+        .filter(login_attempts::Column::KeyValue.eq(key_value))
+        .filter(login_attempts::Column::LastFailedAt.lt(now - Duration::minutes(ATTEMPT_WINDOW_MINUTES)))
+        .filter(
+            Condition::any()
+                .add(login_attempts::Column::LockedUntil.is_null())
+                .add(login_attempts::Column::LockedUntil.lte(now)),
+        )
+        .exec(db)
+        .await?;
+
+    let bump = || {
+        login_attempts::Entity::update_many()
+            .col_expr(login_attempts::Column::FailedCount, Expr::col(login_attempts::Column::FailedCount).add(1))
+            .col_expr(login_attempts::Column::LastFailedAt, Expr::value(now))
+            .filter(login_attempts::Column::KeyType.eq(key_type))
+            .filter(login_attempts::Column::KeyValue.eq(key_value))
+            .exec(db)
+    };
+    if bump().await?.rows_affected > 0 {
+        return Ok(());
+    }
+
+    let counter = login_attempts::ActiveModel {
+        key_type: Set(key_type.to_string()),
+        key_value: Set(key_value.to_string()),
+        failed_count: Set(1),
+        last_failed_at: Set(now),
+        ..Default::default()
+    };
+    if counter.insert(db).await.is_err() {
+        // A concurrent failure created the row first; the unique key makes the insert fail
+        bump().await?;
+    }
+    Ok(())
+}
+
+/// Count a failed attempt against the email and the IP, locking them out past the limit
+pub async fn record_failure(
+    db: &DatabaseConnection,
+    email: &str,
+    ip: &str,
+    user_id: Option<i32>,
+) -> Result<(), DbErr> {
+    let email = normalize_email(email);
+    for (key_type, key_value) in [(KEY_EMAIL, email.clone()), (KEY_IP, ip.to_string())] {
+        increment(db, key_type, &key_value).await?;
+
+        let counter = match find_counter(db, key_type, &key_value).await? {
+            Some(counter) => counter,
+            None => continue,
+        };
+        if counter.failed_count < max_attempts(key_type) {
+            continue;
+        }
+
+        // Only the request that actually sets the lock records the lockout event
+        let now = Utc::now();
+        let locked_until = now + lockout_duration();
+        let locked = login_attempts::Entity::update_many()
+            .col_expr(login_attempts::Column::LockedUntil, Expr::value(locked_until))
+            .filter(login_attempts::Column::Id.eq(counter.id))
+            .filter(
+                Condition::any()
+                    .add(login_attempts::Column::LockedUntil.is_null())
+                    .add(login_attempts::Column::LockedUntil.lte(now)),
+            )
+            .exec(db)
+            .await?;
+
+        if locked.rows_affected > 0 {
+            let event = lockout_events::ActiveModel {
+                user_id: Set(user_id),
+                email: Set(email.clone()),
+                ip_address: Set(ip.to_string()),
+                reason: Set(key_type.to_string()),
+                failed_count: Set(counter.failed_count),
+                locked_until: Set(locked_until),
+                created_at: Set(now),
+                ..Default::default()
+            };
+            event.insert(db).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Clear the per-email counter after a successful login. The per-IP counter is kept so a
+/// valid account cannot be used to reset an attacker's budget.
+pub async fn record_success(db: &DatabaseConnection, email: &str) -> Result<(), DbErr> {
+    login_attempts::Entity::delete_many()
+        .filter(login_attempts::Column::KeyType.eq(KEY_EMAIL))
+        .filter(login_attempts::Column::KeyValue.eq(normalize_email(email)))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Lift lockouts for an email and/or IP and mark the matching events as unlocked
+pub async fn unlock(
+    db: &DatabaseConnection,
+    email: Option<&str>,
+    ip: Option<&str>,
+    admin_id: i32,
+) -> Result<u64, DbErr> {
+    let mut unlocked = 0;
+    let keys = [
+        (KEY_EMAIL, email.map(normalize_email)),
+        (KEY_IP, ip.map(|ip| ip.to_string())),
+    ];
+
+    for (key_type, key_value) in keys {
+        let key_value = match key_value {
+            Some(key_value) => key_value,
+            None => continue,
+        };
+
+        unlocked += login_attempts::Entity::delete_many()
+            .filter(login_attempts::Column::KeyType.eq(key_type))
+            .filter(login_attempts::Column::KeyValue.eq(key_value.clone()))
+            .exec(db)
+            .await?
+            .rows_affected;
+
+        let column = if key_type == KEY_EMAIL {
+            lockout_events::Column::Email
+        } else {
+            lockout_events::Column::IpAddress
+        };
+        lockout_events::Entity::update_many()
+            .col_expr(lockout_events::Column::UnlockedAt, Expr::value(Utc::now()))
+            .col_expr(lockout_events::Column::UnlockedBy, Expr::value(admin_id))
+            .filter(column.eq(key_value))
+            .filter(lockout_events::Column::Reason.eq(key_type))
+            .filter(lockout_events::Column::UnlockedAt.is_null())
+            .filter(lockout_events::Column::LockedUntil.gt(Utc::now()))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(unlocked)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::join_all;
+    use sea_orm::EntityTrait;
+    use super::*;
+    use crate::test_support;
+
+    #[actix_web::test]
+    async fn concurrent_failures_all_count_towards_the_lockout() {
+        let db = test_support::database().await;
+        let max = max_attempts(KEY_EMAIL);
+
+        let failures = (0..max).map(|_| record_failure(&db, "burst@example.com", "203.0.113.7", None));
+        for result in join_all(failures).await {
+            result.unwrap();
+        }
+
+        let counter = find_counter(&db, KEY_EMAIL, "burst@example.com").await.unwrap().unwrap();
+        assert_eq!(counter.failed_count, max);
+        assert!(counter.locked_until.is_some());
+        assert!(check(&db, "burst@example.com", "198.51.100.1").await.unwrap().is_some());
+
+        let events = lockout_events::Entity::find().all(&db).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].reason, KEY_EMAIL);
+    }
+
+    #[actix_web::test]
+    async fn stale_failures_start_over() {
+        let db = test_support::database().await;
+        record_failure(&db, "stale@example.com", "203.0.113.8", None).await.unwrap();
+        login_attempts::Entity::update_many()
+            .col_expr(
+                login_attempts::Column::LastFailedAt,
+                Expr::value(Utc::now() - Duration::minutes(ATTEMPT_WINDOW_MINUTES + 1)),
+            )
+            .exec(&db)
+            .await
+            .unwrap();
+
+        record_failure(&db, "stale@example.com", "203.0.113.8", None).await.unwrap();
+        let counter = find_counter(&db, KEY_EMAIL, "stale@example.com").await.unwrap().unwrap();
+        assert_eq!(counter.failed_count, 1);
+    }
+}
@@ -0,0 +1,167 @@
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::Validation;
+use lazy_static::lazy_static;
+use sea_orm::{DatabaseConnection, DbErr};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use uuid::Uuid;
+use crate::auth::{purpose_token, revoked_token};
+use crate::entities::users;
+use crate::mail::mailer::Mailer;
+
+const PURPOSE: &str = "magic_link";
+const DEFAULT_MAGIC_LINK_TTL_MINUTES: i64 = 15;
+const DEFAULT_MAGIC_LINK_MAX_PER_EMAIL: usize = 5;
+const DEFAULT_MAGIC_LINK_WINDOW_MINUTES: i64 = 15;
+
+/// Reasons a login link cannot be redeemed
+#[derive(Debug)]
+pub enum MagicLinkError {
+    /// The token is forged, expired, already used or was sent to another address
+    Invalid,
+    /// Too many links were redeemed for this email recently; retry after the given seconds
+    RateLimited(i64),
+    Db(DbErr),
+}
+
+impl From<DbErr> for MagicLinkError {
+    fn from(err: DbErr) -> Self {
+        MagicLinkError::Db(err)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MagicLinkClaims {
+    sub: String,
+    email: String,
+    purpose: String,
+    jti: String,
+    exp: usize,
+}
+
+/// Which endpoint a rate limit entry counts against
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Send,
+    Redeem,
+}
+
+/// Recent sends and redemptions per action and normalised email
+type Attempts = HashMap<(Action, String), Vec<DateTime<Utc>>>;
+
+lazy_static! {
+    static ref ATTEMPTS: Mutex<Attempts> = Mutex::new(HashMap::new());
+}
+
+/// Lifetime of a login link, configurable through MAGIC_LINK_TTL_MINUTES
+pub fn link_ttl() -> Duration {
+    let minutes = env::var("MAGIC_LINK_TTL_MINUTES")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_MAGIC_LINK_TTL_MINUTES);
+    Duration::minutes(minutes)
+}
+
+fn max_per_email() -> usize {
+    env::var("MAGIC_LINK_MAX_PER_EMAIL")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAGIC_LINK_MAX_PER_EMAIL)
+}
+
+fn rate_window() -> Duration {
+    let minutes = env::var("MAGIC_LINK_WINDOW_MINUTES")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_MAGIC_LINK_WINDOW_MINUTES);
+    Duration::minutes(minutes)
+}
+
+/// Count an attempt against the email, or return the seconds until another is allowed.
+/// The limit applies whether or not the address belongs to an account.
+pub fn throttle(action: Action, email: &str) -> Option<i64> {
+    let now = Utc::now();
+    let window_start = now - rate_window();
+    let mut attempts = ATTEMPTS.lock().unwrap();
+
+    attempts.retain(|_, times| {
+        times.retain(|time| *time > window_start);
+        !times.is_empty()
+    });
+
+    let times = attempts.entry((action, email.trim().to_lowercase())).or_default();
+    if times.len() >= max_per_email() {
+        let retry_after = (times[0] - window_start).num_seconds().max(1);
+        return Some(retry_after);
+    }
+
+    times.push(now);
+    None
+}
+
+/// Create a signed login token bound to the user and their current address
+pub fn create_token(user: &users::Model) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = MagicLinkClaims {
+        sub: user.id.to_string(),
+        email: user.email.clone(),
+        purpose: PURPOSE.to_string(),
+        jti: Uuid::new_v4().to_string(),
+        exp: (Utc::now() + link_ttl()).timestamp() as usize,
+    };
+
+    purpose_token::sign(&claims)
+}
+
+/// Validate a login token, burn it and return the user id and email it was issued for
+pub async fn redeem(db: &DatabaseConnection, token: &str) -> Result<(i32, String), MagicLinkError> {
+    // Expiry is checked by hand below so redemptions of stale links still count against the email
+    let mut validation = Validation::default();
+    validation.validate_exp = false;
+
+    let claims = purpose_token::decode::<MagicLinkClaims>(token, &validation).map_err(|_| MagicLinkError::Invalid)?;
+
+    if claims.purpose != PURPOSE {
+        return Err(MagicLinkError::Invalid);
+    }
+
+    if let Some(retry_after) = throttle(Action::Redeem, &claims.email) {
+        return Err(MagicLinkError::RateLimited(retry_after));
+    }
+
+    let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).ok_or(MagicLinkError::Invalid)?;
+    if expires_at <= Utc::now() {
+        return Err(MagicLinkError::Invalid);
+    }
+
+    let user_id = claims.sub.parse::<i32>().map_err(|_| MagicLinkError::Invalid)?;
+
+    if revoked_token::is_revoked(db, &claims.jti).await? {
+        return Err(MagicLinkError::Invalid);
+    }
+
+    // The jti column is unique, so a concurrent second redemption fails here
+    if let Err(e) = revoked_token::revoke_jti(db, &claims.jti, user_id, expires_at).await {
+        return match revoked_token::is_revoked(db, &claims.jti).await? {
+            true => Err(MagicLinkError::Invalid),
+            false => Err(MagicLinkError::Db(e)),
+        };
+    }
+
+    Ok((user_id, claims.email))
+}
+
+/// Email a login link pointing at the frontend
+pub async fn send_login_email(user: &users::Model) -> Result<(), String> {
+    let token = create_token(user).map_err(|e| format!("Failed to sign login token: {}", e))?;
+    let frontend_url = env::var("FRONTEND_URL").expect("FRONTEND_URL must be set");
+    let link = format!("{}/magic-login?token={}", frontend_url.trim_end_matches('/'), token);
+
+    let body = format!(
+        "Hi {},\n\nOpen the link below to log in to proctodot:\n\n{}\n\nThe link expires in {} minutes and can only be used once. If you did not ask to log in, you can ignore this email.",
+        user.first_name, link, link_ttl().num_minutes()
+    );
+
+    Mailer::send(&user.email, "Your proctodot login link", body).await
+}
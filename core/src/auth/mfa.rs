@@ -0,0 +1,289 @@
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::Validation;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sea_orm::sea_query::{Condition, Expr};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::auth::{purpose_token, revoked_token};
+use crate::auth::token::hash_token;
+use crate::auth::totp;
+use crate::entities::{mfa_recovery_codes, user_mfa, users};
+use crate::enums::user_type::UserType;
+
+/// Purpose of the intermediate token returned by a password login that still needs a code
+pub const PURPOSE_CHALLENGE: &str = "mfa_required";
+/// Purpose of the intermediate token for users who must enrol before they can sign in
+pub const PURPOSE_ENROLL: &str = "mfa_enroll";
+
+const MFA_TOKEN_TTL_MINUTES: i64 = 5;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(Debug)]
+pub enum MfaError {
+    /// The user has no (pending or active) TOTP enrolment
+    NotEnrolled,
+    /// The user already has TOTP enabled
+    AlreadyEnabled,
+    /// The code did not match, was replayed, or the recovery code was already used
+    InvalidCode,
+    Encryption(&'static str),
+    Db(DbErr),
+}
+
+impl From<DbErr> for MfaError {
+    fn from(err: DbErr) -> Self {
+        MfaError::Db(err)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MfaClaims {
+    sub: String,
+    purpose: String,
+    jti: String,
+    exp: usize,
+}
+
+/// A validated intermediate token
+pub struct MfaToken {
+    pub user_id: i32,
+    jti: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Whether users of this type must use two-factor authentication, configured
+/// as a comma separated list in MFA_REQUIRED_USER_TYPES (e.g. "proctor,admin")
+pub fn required_for(user_type: &UserType) -> bool {
+    env::var("MFA_REQUIRED_USER_TYPES")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|value| UserType::from_str(value.trim()).ok())
+        .any(|required| &required == user_type)
+}
+
+/// Sign a short-lived intermediate token that can only be exchanged on the MFA endpoints
+pub fn create_mfa_token(user_id: i32, purpose: &str) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = MfaClaims {
+        sub: user_id.to_string(),
+        purpose: purpose.to_string(),
+        jti: Uuid::new_v4().to_string(),
+        exp: (Utc::now() + Duration::minutes(MFA_TOKEN_TTL_MINUTES)).timestamp() as usize,
+    };
+
+    purpose_token::sign(&claims)
+}
+
+/// Validate an intermediate token for the expected purpose
+pub fn verify_mfa_token(token: &str, purpose: &str) -> Result<MfaToken, &'static str> {
+    let claims = purpose_token::decode::<MfaClaims>(token, &Validation::default())
+        .map_err(|_| "Invalid or expired MFA token")?;
+
+    if claims.purpose != purpose {
+        return Err("Invalid or expired MFA token");
+    }
+
+    Ok(MfaToken {
+        user_id: claims.sub.parse::<i32>().map_err(|_| "Invalid or expired MFA token")?,
+        jti: claims.jti,
+        expires_at: DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or_else(Utc::now),
+    })
+}
+
+/// Whether an intermediate token has already been exchanged for a session
+pub async fn is_consumed(db: &DatabaseConnection, token: &MfaToken) -> Result<bool, DbErr> {
+    revoked_token::is_revoked(db, &token.jti).await
+}
+
+/// Burn an intermediate token once its code has been accepted. The denylist is unique on the
+/// token id, so of two concurrent exchanges only the first one succeeds.
+pub async fn consume(db: &DatabaseConnection, token: &MfaToken) -> Result<(), DbErr> {
+    revoked_token::revoke_jti(db, &token.jti, token.user_id, token.expires_at).await
+}
+
+async fn find_enrollment(db: &DatabaseConnection, user_id: i32) -> Result<Option<user_mfa::Model>, DbErr> {
+    user_mfa::Entity::find()
+        .filter(user_mfa::Column::UserId.eq(user_id))
+        .one(db)
+        .await
+}
+
+/// Decide whether a password login must be completed with a second factor.
+/// Returns the intermediate response to send instead of the real token pair.
+pub async fn login_challenge(
+    db: &DatabaseConnection,
+    user: &users::Model,
+) -> Result<Option<serde_json::Value>, DbErr> {
+    let enabled = find_enrollment(db, user.id)
+        .await?
+        .is_some_and(|enrollment| enrollment.enabled_at.is_some());
+    let required = UserType::from_str(&user.r#type)
+        .map(|user_type| required_for(&user_type))
+        .unwrap_or(false);
+
+    if !enabled && !required {
+        return Ok(None);
+    }
+
+    let purpose = if enabled { PURPOSE_CHALLENGE } else { PURPOSE_ENROLL };
+    let mfa_token = create_mfa_token(user.id, purpose)
+        .map_err(|e| DbErr::Custom(format!("Failed to sign MFA token: {}", e)))?;
+
+    Ok(Some(serde_json::json!({
+        "mfa_required": true,
+        "enrollment_required": !enabled,
+        "mfa_token": mfa_token,
+    })))
+}
+
+/// Start (or restart) TOTP enrolment and return the secret with its provisioning URI
+pub async fn begin_enrollment(
+    db: &DatabaseConnection,
+    user: &users::Model,
+) -> Result<serde_json::Value, MfaError> {
+    if let Some(existing) = find_enrollment(db, user.id).await? {
+        if existing.enabled_at.is_some() {
+            return Err(MfaError::AlreadyEnabled);
+        }
+        user_mfa::Entity::delete_by_id(existing.id).exec(db).await?;
+    }
+
+    let secret = totp::generate_secret();
+    let mut enrollment = user_mfa::Model {
+        user_id: user.id,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        ..Default::default()
+    };
+    enrollment.set_secret(&secret).map_err(MfaError::Encryption)?;
+    let active_enrollment: user_mfa::ActiveModel = enrollment.into();
+    user_mfa::Entity::insert(active_enrollment).exec(db).await?;
+
+    Ok(serde_json::json!({
+        "secret": secret,
+        "provisioning_uri": totp::provisioning_uri(&secret, &user.email),
+    }))
+}
+
+/// Record a TOTP step as used, failing if a concurrent request already used it
+async fn mark_step_used(db: &DatabaseConnection, enrollment_id: i32, step: i64) -> Result<bool, DbErr> {
+    let result = user_mfa::Entity::update_many()
+        .col_expr(user_mfa::Column::LastUsedStep, Expr::value(step))
+        .col_expr(user_mfa::Column::UpdatedAt, Expr::value(Utc::now()))
+        .filter(user_mfa::Column::Id.eq(enrollment_id))
+        .filter(
+            Condition::any()
+                .add(user_mfa::Column::LastUsedStep.is_null())
+                .add(user_mfa::Column::LastUsedStep.lt(step)),
+        )
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// Confirm a pending enrolment with a first code, enable it and issue recovery codes
+pub async fn activate(db: &DatabaseConnection, user_id: i32, code: &str) -> Result<Vec<String>, MfaError> {
+    let enrollment = find_enrollment(db, user_id).await?.ok_or(MfaError::NotEnrolled)?;
+    if enrollment.enabled_at.is_some() {
+        return Err(MfaError::AlreadyEnabled);
+    }
+
+    let secret = enrollment.get_secret().map_err(MfaError::Encryption)?;
+    let step = totp::verify(&secret, code, enrollment.last_used_step).ok_or(MfaError::InvalidCode)?;
+
+    let mut active_enrollment: user_mfa::ActiveModel = enrollment.into();
+    active_enrollment.enabled_at = Set(Some(Utc::now()));
+    active_enrollment.last_used_step = Set(Some(step));
+    active_enrollment.updated_at = Set(Utc::now());
+    active_enrollment.update(db).await?;
+
+    Ok(regenerate_recovery_codes(db, user_id).await?)
+}
+
+/// Verify a TOTP code or an unused recovery code for a user with TOTP enabled
+pub async fn verify_code(db: &DatabaseConnection, user_id: i32, code: &str) -> Result<(), MfaError> {
+    let enrollment = match find_enrollment(db, user_id).await? {
+        Some(enrollment) if enrollment.enabled_at.is_some() => enrollment,
+        _ => return Err(MfaError::NotEnrolled),
+    };
+
+    let secret = enrollment.get_secret().map_err(MfaError::Encryption)?;
+    if let Some(step) = totp::verify(&secret, code, enrollment.last_used_step) {
+        return if mark_step_used(db, enrollment.id, step).await? {
+            Ok(())
+        } else {
+            Err(MfaError::InvalidCode)
+        };
+    }
+
+    // Fall back to a single-use recovery code
+    let result = mfa_recovery_codes::Entity::update_many()
+        .col_expr(mfa_recovery_codes::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(mfa_recovery_codes::Column::UserId.eq(user_id))
+        .filter(mfa_recovery_codes::Column::CodeHash.eq(hash_token(&normalize_recovery_code(code))))
+        .filter(mfa_recovery_codes::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    if result.rows_affected > 0 {
+        Ok(())
+    } else {
+        Err(MfaError::InvalidCode)
+    }
+}
+
+/// Remove the TOTP enrolment and recovery codes of a user
+pub async fn disable(db: &DatabaseConnection, user_id: i32) -> Result<(), DbErr> {
+    user_mfa::Entity::delete_many()
+        .filter(user_mfa::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    mfa_recovery_codes::Entity::delete_many()
+        .filter(mfa_recovery_codes::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Replace all recovery codes of a user and return the new plain codes, which are never stored
+pub async fn regenerate_recovery_codes(db: &DatabaseConnection, user_id: i32) -> Result<Vec<String>, DbErr> {
+    mfa_recovery_codes::Entity::delete_many()
+        .filter(mfa_recovery_codes::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+    let records = codes.iter().map(|code| mfa_recovery_codes::ActiveModel {
+        user_id: Set(user_id),
+        code_hash: Set(hash_token(&normalize_recovery_code(code))),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    });
+    mfa_recovery_codes::Entity::insert_many(records).exec(db).await?;
+
+    Ok(codes)
+}
+
+/// Generate a recovery code formatted as four groups of four hex characters
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    let encoded = hex::encode(bytes);
+    encoded
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Ignore separators, whitespace and case so codes can be typed loosely
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
@@ -0,0 +1,26 @@
+pub mod token;
+pub mod jwt;
+pub mod refresh_token;
+pub mod revoked_token;
+pub mod email_verification;
+pub mod password;
+pub mod password_reset;
+pub mod password_policy;
+pub mod totp;
+pub mod mfa;
+pub mod passkey;
+pub mod oidc;
+pub mod wallet_challenge;
+pub mod signing_keys;
+pub mod purpose_token;
+pub mod login_throttle;
+pub mod invitation;
+pub mod api_key;
+pub mod ws_ticket;
+pub mod impersonation;
+pub mod magic_link;
+pub mod session;
+pub mod account;
+pub mod contact_change;
+pub mod data_export;
+pub mod rate_limit;
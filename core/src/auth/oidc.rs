@@ -0,0 +1,281 @@
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use oauth2::basic::BasicClient;
+use oauth2::reqwest::async_http_client;
+use oauth2::{
+    AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, Scope, TokenResponse, TokenUrl,
+};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use crate::entities::{user_identities, users};
+use crate::enums::user_type::UserType;
+
+const PENDING_LOGIN_TTL_MINUTES: i64 = 10;
+const DEFAULT_CHAIN: &str = "polkadot";
+
+/// PKCE verifier kept between the authorize redirect and the callback
+struct PendingLogin {
+    provider: String,
+    pkce_verifier: String,
+    expires_at: DateTime<Utc>,
+}
+
+lazy_static! {
+    static ref PENDING_LOGINS: Mutex<HashMap<String, PendingLogin>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug)]
+pub enum OidcError {
+    /// The provider is not listed in OIDC_PROVIDERS or is missing settings
+    UnknownProvider,
+    /// The state is unknown, expired or was issued for another provider
+    InvalidState,
+    /// The provider did not return an email address we can attach to an account
+    MissingEmail,
+    /// An account with this email exists but the provider has not verified the address
+    UnverifiedEmail,
+    /// Talking to the provider failed (discovery, code exchange or userinfo)
+    Provider(String),
+    /// The identity is linked to an account that has been deleted
+    AccountDeleted,
+    /// The email belongs to a proctor or admin, which are never linked to a provider automatically
+    PrivilegedAccount,
+    Db(DbErr),
+}
+
+impl From<DbErr> for OidcError {
+    fn from(err: DbErr) -> Self {
+        OidcError::Db(err)
+    }
+}
+
+/// A provider configured through OIDC_<NAME>_* environment variables
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub chain: String,
+}
+
+impl OidcProvider {
+    /// Load a provider listed in the comma separated OIDC_PROVIDERS variable
+    pub fn from_env(name: &str) -> Option<Self> {
+        let enabled = env::var("OIDC_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .any(|provider| provider.trim() == name);
+        if !enabled {
+            return None;
+        }
+
+        let prefix = format!("OIDC_{}", name.to_uppercase().replace('-', "_"));
+        let setting = |key: &str| env::var(format!("{}_{}", prefix, key)).ok();
+
+        let redirect_url = setting("REDIRECT_URL").unwrap_or_else(|| {
+            let frontend_url = env::var("FRONTEND_URL").expect("FRONTEND_URL must be set");
+            format!("{}/oidc/{}/callback", frontend_url.trim_end_matches('/'), name)
+        });
+
+        Some(OidcProvider {
+            name: name.to_string(),
+            issuer_url: setting("ISSUER_URL")?.trim_end_matches('/').to_string(),
+            client_id: setting("CLIENT_ID")?,
+            client_secret: setting("CLIENT_SECRET"),
+            redirect_url,
+            chain: setting("CHAIN").unwrap_or_else(|| DEFAULT_CHAIN.to_string()),
+        })
+    }
+
+    async fn discover(&self) -> Result<ProviderMetadata, OidcError> {
+        let url = format!("{}/.well-known/openid-configuration", self.issuer_url);
+        let metadata: ProviderMetadata = reqwest::get(&url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| OidcError::Provider(format!("Discovery request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| OidcError::Provider(format!("Invalid discovery document: {}", e)))?;
+
+        if metadata.issuer.trim_end_matches('/') != self.issuer_url {
+            return Err(OidcError::Provider("Discovery document issuer mismatch".to_string()));
+        }
+
+        Ok(metadata)
+    }
+
+    fn client(&self, metadata: &ProviderMetadata) -> Result<BasicClient, OidcError> {
+        let invalid = |e: oauth2::url::ParseError| OidcError::Provider(format!("Invalid provider URL: {}", e));
+        Ok(BasicClient::new(
+            ClientId::new(self.client_id.clone()),
+            self.client_secret.clone().map(ClientSecret::new),
+            AuthUrl::new(metadata.authorization_endpoint.clone()).map_err(invalid)?,
+            Some(TokenUrl::new(metadata.token_endpoint.clone()).map_err(invalid)?),
+        )
+        .set_redirect_uri(RedirectUrl::new(self.redirect_url.clone()).map_err(invalid)?))
+    }
+}
+
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+/// Claims returned by the provider's userinfo endpoint
+#[derive(Debug, Deserialize)]
+pub struct UserInfo {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+    pub name: Option<String>,
+    pub phone_number: Option<String>,
+}
+
+/// Build the authorization URL for an authorization-code + PKCE login
+pub async fn authorization_url(provider: &OidcProvider) -> Result<String, OidcError> {
+    let metadata = provider.discover().await?;
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+    let (url, state) = provider
+        .client(&metadata)?
+        .authorize_url(CsrfToken::new_random)
+        .add_scope(Scope::new("openid".to_string()))
+        .add_scope(Scope::new("email".to_string()))
+        .add_scope(Scope::new("profile".to_string()))
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+
+    let mut pending = PENDING_LOGINS.lock().unwrap();
+    // Abandoned logins are dropped whenever a new one starts
+    pending.retain(|_, login| login.expires_at > Utc::now());
+    pending.insert(
+        state.secret().clone(),
+        PendingLogin {
+            provider: provider.name.clone(),
+            pkce_verifier: pkce_verifier.secret().clone(),
+            expires_at: Utc::now() + Duration::minutes(PENDING_LOGIN_TTL_MINUTES),
+        },
+    );
+
+    Ok(url.to_string())
+}
+
+/// Exchange the authorization code and fetch the user's claims from the provider
+pub async fn exchange_code(provider: &OidcProvider, code: &str, state: &str) -> Result<UserInfo, OidcError> {
+    let pending = match PENDING_LOGINS.lock().unwrap().remove(state) {
+        Some(pending) if pending.provider == provider.name && pending.expires_at > Utc::now() => pending,
+        _ => return Err(OidcError::InvalidState),
+    };
+
+    let metadata = provider.discover().await?;
+    let token = provider
+        .client(&metadata)?
+        .exchange_code(AuthorizationCode::new(code.to_string()))
+        .set_pkce_verifier(PkceCodeVerifier::new(pending.pkce_verifier))
+        .request_async(async_http_client)
+        .await
+        .map_err(|e| OidcError::Provider(format!("Code exchange failed: {}", e)))?;
+
+    reqwest::Client::new()
+        .get(&metadata.userinfo_endpoint)
+        .bearer_auth(token.access_token().secret())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| OidcError::Provider(format!("Userinfo request failed: {}", e)))?
+        .json::<UserInfo>()
+        .await
+        .map_err(|e| OidcError::Provider(format!("Invalid userinfo response: {}", e)))
+}
+
+/// Map the provider identity onto a user, creating the user on first login.
+/// Returns the user and whether it was newly created.
+pub async fn find_or_create_user(
+    db: &DatabaseConnection,
+    provider: &OidcProvider,
+    info: &UserInfo,
+) -> Result<(users::Model, bool), OidcError> {
+    let identity = user_identities::Entity::find()
+        .filter(user_identities::Column::Provider.eq(provider.name.clone()))
+        .filter(user_identities::Column::Subject.eq(info.sub.clone()))
+        .one(db)
+        .await?;
+
+    if let Some(identity) = identity {
+        let user = users::Entity::find_active_by_id(identity.user_id)
+            .one(db)
+            .await?
+            .ok_or(OidcError::AccountDeleted)?;
+        return Ok((user, false));
+    }
+
+    let email = info.email.clone().ok_or(OidcError::MissingEmail)?;
+    let existing = users::Entity::find_active()
+        .filter(users::Column::Email.eq(email.clone()))
+        .one(db)
+        .await?;
+
+    let (user, created) = match existing {
+        // Only link to an existing account when the provider vouches for the address
+        Some(_) if !info.email_verified => return Err(OidcError::UnverifiedEmail),
+        // A provider login must not be a way into an account with elevated rights
+        Some(user) if user.r#type != UserType::Member.to_string() => return Err(OidcError::PrivilegedAccount),
+        Some(user) => (user, false),
+        None => {
+            let (first_name, last_name) = split_name(info);
+            let new_user = users::ActiveModel {
+                r#type: Set(UserType::Member.to_string()),
+                chain: Set(provider.chain.clone()),
+                email: Set(email.clone()),
+                first_name: Set(first_name),
+                last_name: Set(last_name),
+                phone: Set(info.phone_number.clone().unwrap_or_default()),
+                verified_at: Set(info.email_verified.then(Utc::now)),
+                created_at: Set(Utc::now()),
+                updated_at: Set(Utc::now()),
+                ..Default::default()
+            };
+            (new_user.insert(db).await?, true)
+        }
+    };
+
+    let identity = user_identities::ActiveModel {
+        user_id: Set(user.id),
+        provider: Set(provider.name.clone()),
+        subject: Set(info.sub.clone()),
+        email: Set(Some(email)),
+        created_at: Set(Utc::now()),
+        updated_at: Set(Utc::now()),
+        ..Default::default()
+    };
+    identity.insert(db).await?;
+
+    Ok((user, created))
+}
+
+/// Prefer the structured name claims and fall back to splitting `name`
+fn split_name(info: &UserInfo) -> (String, String) {
+    if info.given_name.is_some() || info.family_name.is_some() {
+        return (
+            info.given_name.clone().unwrap_or_default(),
+            info.family_name.clone().unwrap_or_default(),
+        );
+    }
+
+    let name = info.name.clone().unwrap_or_default();
+    match name.split_once(' ') {
+        Some((first, last)) => (first.to_string(), last.to_string()),
+        None => (name, String::new()),
+    }
+}
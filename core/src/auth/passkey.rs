@@ -0,0 +1,222 @@
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use webauthn_rs::prelude::*;
+use crate::auth::rate_limit::RateLimit;
+use crate::auth::token::generate_token;
+use crate::entities::{passkey_credentials, users};
+
+const CEREMONY_TTL_MINUTES: i64 = 5;
+/// Upper bound on ceremonies held in memory, since login ceremonies start unauthenticated
+const MAX_PENDING_CEREMONIES: usize = 10_000;
+
+/// Server-side state of a ceremony that is waiting for the authenticator's response
+enum PendingCeremony {
+    Registration { user_id: i32, state: PasskeyRegistration },
+    Authentication { user_id: i32, state: PasskeyAuthentication },
+}
+
+lazy_static! {
+    static ref CEREMONIES: Mutex<HashMap<String, (PendingCeremony, DateTime<Utc>)>> =
+        Mutex::new(HashMap::new());
+    static ref LOGIN_STARTS_PER_IP: RateLimit =
+        RateLimit::from_env("PASSKEY_LOGIN_MAX_PER_IP", 30, Duration::minutes(5));
+}
+
+#[derive(Debug)]
+pub enum PasskeyError {
+    /// The ceremony id is unknown, expired or belongs to another ceremony type or user
+    UnknownCeremony,
+    /// The user has no registered passkeys
+    NoCredentials,
+    /// Too many ceremonies are waiting for a response; try again once some expire
+    Busy,
+    /// The authenticator response failed verification
+    Webauthn(WebauthnError),
+    Serialization(serde_json::Error),
+    Db(DbErr),
+}
+
+impl From<DbErr> for PasskeyError {
+    fn from(err: DbErr) -> Self {
+        PasskeyError::Db(err)
+    }
+}
+
+impl From<WebauthnError> for PasskeyError {
+    fn from(err: WebauthnError) -> Self {
+        PasskeyError::Webauthn(err)
+    }
+}
+
+impl From<serde_json::Error> for PasskeyError {
+    fn from(err: serde_json::Error) -> Self {
+        PasskeyError::Serialization(err)
+    }
+}
+
+/// Build the relying party from WEBAUTHN_RP_ORIGIN (default FRONTEND_URL) and
+/// WEBAUTHN_RP_ID (default the origin's host)
+fn webauthn() -> Result<Webauthn, WebauthnError> {
+    let origin = env::var("WEBAUTHN_RP_ORIGIN")
+        .or_else(|_| env::var("FRONTEND_URL"))
+        .expect("WEBAUTHN_RP_ORIGIN or FRONTEND_URL must be set");
+    let origin = Url::parse(&origin).map_err(|_| WebauthnError::Configuration)?;
+    let rp_id = env::var("WEBAUTHN_RP_ID")
+        .ok()
+        .or_else(|| origin.host_str().map(|host| host.to_string()))
+        .ok_or(WebauthnError::Configuration)?;
+
+    WebauthnBuilder::new(&rp_id, &origin)?
+        .rp_name("proctodot")
+        .build()
+}
+
+/// Stable WebAuthn user handle derived from the user id
+fn user_handle(user_id: i32) -> Uuid {
+    Uuid::from_u128(user_id as u128)
+}
+
+/// Count a login ceremony start against the client IP, or return the seconds until another is allowed
+pub fn throttle_login(ip: &str) -> Option<i64> {
+    LOGIN_STARTS_PER_IP.hit(ip)
+}
+
+fn store_ceremony(ceremony: PendingCeremony) -> Result<String, PasskeyError> {
+    let ceremony_id = generate_token();
+    let mut ceremonies = CEREMONIES.lock().unwrap();
+    // Abandoned ceremonies are dropped whenever a new one starts
+    ceremonies.retain(|_, (_, expires_at)| *expires_at > Utc::now());
+    if ceremonies.len() >= MAX_PENDING_CEREMONIES {
+        return Err(PasskeyError::Busy);
+    }
+    ceremonies.insert(
+        ceremony_id.clone(),
+        (ceremony, Utc::now() + Duration::minutes(CEREMONY_TTL_MINUTES)),
+    );
+    Ok(ceremony_id)
+}
+
+fn take_ceremony(ceremony_id: &str) -> Result<PendingCeremony, PasskeyError> {
+    match CEREMONIES.lock().unwrap().remove(ceremony_id) {
+        Some((ceremony, expires_at)) if expires_at > Utc::now() => Ok(ceremony),
+        _ => Err(PasskeyError::UnknownCeremony),
+    }
+}
+
+async fn load_passkeys(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<Vec<(passkey_credentials::Model, Passkey)>, PasskeyError> {
+    let records = passkey_credentials::Entity::find()
+        .filter(passkey_credentials::Column::UserId.eq(user_id))
+        .all(db)
+        .await?;
+
+    records
+        .into_iter()
+        .map(|record| {
+            let passkey: Passkey = serde_json::from_str(&record.passkey)?;
+            Ok((record, passkey))
+        })
+        .collect()
+}
+
+/// Start the registration ceremony for a signed-in user
+pub async fn start_registration(
+    db: &DatabaseConnection,
+    user: &users::Model,
+) -> Result<(String, CreationChallengeResponse), PasskeyError> {
+    // Stop the same authenticator from being registered twice
+    let exclude_credentials = load_passkeys(db, user.id)
+        .await?
+        .into_iter()
+        .map(|(_, passkey)| passkey.cred_id().clone())
+        .collect::<Vec<_>>();
+
+    let (options, state) = webauthn()?.start_passkey_registration(
+        user_handle(user.id),
+        &user.email,
+        &user.get_full_name(),
+        Some(exclude_credentials),
+    )?;
+
+    let ceremony_id = store_ceremony(PendingCeremony::Registration { user_id: user.id, state })?;
+    Ok((ceremony_id, options))
+}
+
+/// Verify the authenticator's attestation and store the new passkey
+pub async fn finish_registration(
+    db: &DatabaseConnection,
+    user_id: i32,
+    ceremony_id: &str,
+    name: Option<String>,
+    credential: &RegisterPublicKeyCredential,
+) -> Result<passkey_credentials::Model, PasskeyError> {
+    let state = match take_ceremony(ceremony_id)? {
+        PendingCeremony::Registration { user_id: owner, state } if owner == user_id => state,
+        _ => return Err(PasskeyError::UnknownCeremony),
+    };
+
+    let passkey = webauthn()?.finish_passkey_registration(credential, &state)?;
+    let record = passkey_credentials::ActiveModel {
+        user_id: Set(user_id),
+        credential_id: Set(hex::encode(passkey.cred_id())),
+        name: Set(name.unwrap_or_else(|| "Passkey".to_string())),
+        passkey: Set(serde_json::to_string(&passkey)?),
+        created_at: Set(Utc::now()),
+        updated_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    Ok(record.insert(db).await?)
+}
+
+/// Start the authentication ceremony for the user with the given email
+pub async fn start_authentication(
+    db: &DatabaseConnection,
+    user: &users::Model,
+) -> Result<(String, RequestChallengeResponse), PasskeyError> {
+    let passkeys: Vec<Passkey> = load_passkeys(db, user.id)
+        .await?
+        .into_iter()
+        .map(|(_, passkey)| passkey)
+        .collect();
+    if passkeys.is_empty() {
+        return Err(PasskeyError::NoCredentials);
+    }
+
+    let (options, state) = webauthn()?.start_passkey_authentication(&passkeys)?;
+    let ceremony_id = store_ceremony(PendingCeremony::Authentication { user_id: user.id, state })?;
+    Ok((ceremony_id, options))
+}
+
+/// Verify the authenticator's assertion and return the id of the signed-in user
+pub async fn finish_authentication(
+    db: &DatabaseConnection,
+    ceremony_id: &str,
+    credential: &PublicKeyCredential,
+) -> Result<i32, PasskeyError> {
+    let (user_id, state) = match take_ceremony(ceremony_id)? {
+        PendingCeremony::Authentication { user_id, state } => (user_id, state),
+        _ => return Err(PasskeyError::UnknownCeremony),
+    };
+
+    let result = webauthn()?.finish_passkey_authentication(credential, &state)?;
+
+    // Persist the signature counter and backup state reported by the authenticator
+    for (record, mut passkey) in load_passkeys(db, user_id).await? {
+        if passkey.update_credential(&result).is_some() {
+            let mut active_record: passkey_credentials::ActiveModel = record.into();
+            active_record.passkey = Set(serde_json::to_string(&passkey)?);
+            active_record.last_used_at = Set(Some(Utc::now()));
+            active_record.updated_at = Set(Utc::now());
+            active_record.update(db).await?;
+        }
+    }
+
+    Ok(user_id)
+}
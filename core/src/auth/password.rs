@@ -0,0 +1,271 @@
+use actix_web::web;
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, SaltString};
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, PasswordVerifier, Version};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set};
+use std::env;
+use std::fmt;
+use tokio::sync::Semaphore;
+use crate::entities::passwords;
+
+const DEFAULT_PASSWORD_HISTORY_SIZE: u64 = 5;
+
+/// Why a password could not be hashed or checked
+#[derive(Debug)]
+pub enum HashError {
+    Argon2(argon2::password_hash::Error),
+    /// The blocking pool dropped the job
+    Pool,
+}
+
+impl fmt::Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashError::Argon2(e) => write!(f, "{}", e),
+            HashError::Pool => write!(f, "password hashing job was cancelled"),
+        }
+    }
+}
+
+impl From<argon2::password_hash::Error> for HashError {
+    fn from(err: argon2::password_hash::Error) -> Self {
+        HashError::Argon2(err)
+    }
+}
+
+lazy_static! {
+    /// Argon2 cost parameters from ARGON2_MEMORY_KIB, ARGON2_ITERATIONS and ARGON2_PARALLELISM
+    static ref PARAMS: Params = {
+        let cost = |name: &str, default: u32| {
+            env::var(name).ok().and_then(|value| value.parse::<u32>().ok()).unwrap_or(default)
+        };
+        Params::new(
+            cost("ARGON2_MEMORY_KIB", Params::DEFAULT_M_COST),
+            cost("ARGON2_ITERATIONS", Params::DEFAULT_T_COST),
+            cost("ARGON2_PARALLELISM", Params::DEFAULT_P_COST),
+            None,
+        )
+        .expect("Invalid Argon2 parameters")
+    };
+
+    /// Caps concurrent hashes at PASSWORD_HASH_CONCURRENCY (default: one per core) so a
+    /// login storm queues up instead of exhausting memory and the blocking pool
+    static ref HASH_PERMITS: Semaphore = Semaphore::new(
+        env::var("PASSWORD_HASH_CONCURRENCY")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|permits| *permits > 0)
+            .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+    );
+
+    /// Verified against when there is no real hash, so unknown accounts cost as much as known ones
+    static ref DUMMY_HASH: String = {
+        let salt = SaltString::generate(&mut OsRng);
+        argon2()
+            .hash_password(b"dummy password for timing", &salt)
+            .expect("Failed to create dummy password hash")
+            .to_string()
+    };
+}
+
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, PARAMS.clone())
+}
+
+/// Run CPU heavy Argon2 work on the blocking pool, at most HASH_PERMITS at a time
+async fn run_blocking<T, F>(job: F) -> Result<T, HashError>
+where
+    F: FnOnce() -> Result<T, HashError> + Send + 'static,
+    T: Send + 'static,
+{
+    let _permit = HASH_PERMITS.acquire().await.map_err(|_| HashError::Pool)?;
+    web::block(job).await.map_err(|_| HashError::Pool)?
+}
+
+/// Hash a plain text password with Argon2 and a random salt
+pub async fn hash_password(password: &str) -> Result<String, HashError> {
+    let password = password.to_string();
+    run_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(argon2().hash_password(password.as_bytes(), &salt)?.to_string())
+    })
+    .await
+}
+
+/// Check a password against a stored hash. Without a hash (unknown user, no
+/// password set) a dummy hash is verified instead and the check always fails,
+/// so the response time does not reveal whether the account exists.
+pub async fn verify_password(password: &str, password_hash: Option<&str>) -> Result<bool, HashError> {
+    let password = password.to_string();
+    let password_hash = password_hash.map(|hash| hash.to_string());
+    run_blocking(move || {
+        let (hash, known) = match &password_hash {
+            Some(hash) => (hash.as_str(), true),
+            None => (DUMMY_HASH.as_str(), false),
+        };
+        let parsed_hash = match PasswordHash::new(hash) {
+            Ok(parsed_hash) => parsed_hash,
+            Err(e) => {
+                log::error!("Stored password hash is malformed: {}", e);
+                return Ok(false);
+            }
+        };
+        Ok(argon2().verify_password(password.as_bytes(), &parsed_hash).is_ok() && known)
+    })
+    .await
+}
+
+/// Whether a stored hash was made with other parameters than the current ones
+pub fn needs_rehash(password_hash: &str) -> bool {
+    let parsed_hash = match PasswordHash::new(password_hash) {
+        Ok(parsed_hash) => parsed_hash,
+        Err(_) => return true,
+    };
+    if parsed_hash.algorithm != Algorithm::Argon2id.ident() || parsed_hash.version != Some(Version::V0x13.into()) {
+        return true;
+    }
+    match Params::try_from(&parsed_hash) {
+        Ok(params) => {
+            params.m_cost() != PARAMS.m_cost()
+                || params.t_cost() != PARAMS.t_cost()
+                || params.p_cost() != PARAMS.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+/// How many past hashes are kept per user, configurable through PASSWORD_HISTORY_SIZE
+pub fn history_size() -> u64 {
+    env::var("PASSWORD_HISTORY_SIZE")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_PASSWORD_HISTORY_SIZE)
+        .max(1)
+}
+
+/// The user's password hashes, newest first. The first row is the current password.
+pub async fn history(db: &DatabaseConnection, user_id: i32) -> Result<Vec<passwords::Model>, DbErr> {
+    passwords::Entity::find()
+        .filter(passwords::Column::UserId.eq(user_id))
+        .order_by_desc(passwords::Column::CreatedAt)
+        .order_by_desc(passwords::Column::Id)
+        .limit(history_size())
+        .all(db)
+        .await
+}
+
+/// The hash of the user's current password
+pub async fn current(db: &DatabaseConnection, user_id: i32) -> Result<Option<passwords::Model>, DbErr> {
+    passwords::Entity::find()
+        .filter(passwords::Column::UserId.eq(user_id))
+        .order_by_desc(passwords::Column::CreatedAt)
+        .order_by_desc(passwords::Column::Id)
+        .one(db)
+        .await
+}
+
+/// Whether the password matches any of the given hashes, e.g. the user's history
+pub async fn matches_any(password: &str, password_hashes: Vec<String>) -> Result<bool, HashError> {
+    let password = password.to_string();
+    run_blocking(move || {
+        Ok(password_hashes.iter().any(|hash| {
+            PasswordHash::new(hash)
+                .map(|parsed_hash| argon2().verify_password(password.as_bytes(), &parsed_hash).is_ok())
+                .unwrap_or(false)
+        }))
+    })
+    .await
+}
+
+/// Store a new password hash for the user, keeping only the newest PASSWORD_HISTORY_SIZE hashes
+pub async fn replace_password(
+    db: &DatabaseConnection,
+    user_id: i32,
+    password_hash: String,
+) -> Result<(), DbErr> {
+    let new_password = passwords::ActiveModel {
+        user_id: Set(user_id),
+        password_hash: Set(password_hash),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+    passwords::Entity::insert(new_password).exec(db).await?;
+
+    // MySQL has no OFFSET without LIMIT, so skip the kept rows here
+    let expired: Vec<i32> = passwords::Entity::find()
+        .filter(passwords::Column::UserId.eq(user_id))
+        .order_by_desc(passwords::Column::CreatedAt)
+        .order_by_desc(passwords::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .skip(history_size() as usize)
+        .map(|password| password.id)
+        .collect();
+
+    if !expired.is_empty() {
+        passwords::Entity::delete_many()
+            .filter(passwords::Column::Id.is_in(expired))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Swap in a fresh hash of the same password, e.g. after the Argon2 parameters changed
+pub async fn update_hash(db: &DatabaseConnection, password_id: i32, password_hash: String) -> Result<(), DbErr> {
+    passwords::Entity::update_many()
+        .col_expr(passwords::Column::PasswordHash, Expr::value(password_hash))
+        .filter(passwords::Column::Id.eq(password_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn hash_with(algorithm: Algorithm, params: Params) -> String {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::new(algorithm, Version::V0x13, params)
+            .hash_password(b"correct horse battery staple", &salt)
+            .unwrap()
+            .to_string()
+    }
+
+    #[actix_web::test]
+    async fn hashes_with_the_current_parameters_are_kept() {
+        test_support::init_env();
+        let hash = hash_password("correct horse battery staple").await.unwrap();
+        assert!(!needs_rehash(&hash));
+        assert!(!needs_rehash(&hash_with(Algorithm::Argon2id, PARAMS.clone())));
+    }
+
+    #[test]
+    fn hashes_with_other_parameters_are_rehashed() {
+        test_support::init_env();
+        let (m_cost, t_cost, p_cost) = (PARAMS.m_cost(), PARAMS.t_cost(), PARAMS.p_cost());
+        for (m_cost, t_cost, p_cost) in [(m_cost * 2, t_cost, p_cost), (m_cost, t_cost + 1, p_cost), (m_cost, t_cost, p_cost + 1)] {
+            let params = Params::new(m_cost, t_cost, p_cost, None).unwrap();
+            assert!(needs_rehash(&hash_with(Algorithm::Argon2id, params)));
+        }
+        assert!(needs_rehash(&hash_with(Algorithm::Argon2i, PARAMS.clone())));
+        assert!(needs_rehash("not a PHC string"));
+    }
+
+    #[actix_web::test]
+    async fn verifying_without_a_hash_always_fails() {
+        test_support::init_env();
+        assert!(!verify_password("dummy password for timing", None).await.unwrap());
+        assert!(!verify_password("", None).await.unwrap());
+
+        let hash = hash_password("correct horse battery staple").await.unwrap();
+        assert!(verify_password("correct horse battery staple", Some(&hash)).await.unwrap());
+        assert!(!verify_password("wrong horse battery staple", Some(&hash)).await.unwrap());
+    }
+}
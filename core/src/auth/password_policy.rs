@@ -0,0 +1,203 @@
+use actix_web::web;
+use sha1::{Digest, Sha1};
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use crate::enums::user_type::UserType;
+
+/// Longer inputs are refused so hashing cannot be used to tie up the server
+const MAX_PASSWORD_LENGTH: usize = 128;
+/// Hash prefix length of the breached password range files
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+/// Rules a new password has to satisfy
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+}
+
+impl PasswordPolicy {
+    /// Policy for a user type, read from PASSWORD_POLICY_<TYPE>, e.g. `min=12,lower,upper,digit,symbol`
+    pub fn for_user_type(user_type: &UserType) -> Self {
+        let default = match user_type {
+            UserType::Member => "min=8",
+            UserType::Proctor | UserType::Admin => "min=12,lower,upper,digit",
+        };
+        let key = format!("PASSWORD_POLICY_{}", user_type.to_string().to_uppercase());
+        Self::parse(&env::var(key).unwrap_or_else(|_| default.to_string()))
+    }
+
+    fn parse(rules: &str) -> Self {
+        let mut policy = PasswordPolicy {
+            min_length: 8,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+        };
+
+        for rule in rules.split(',').map(str::trim) {
+            match rule {
+                "lower" => policy.require_lowercase = true,
+                "upper" => policy.require_uppercase = true,
+                "digit" => policy.require_digit = true,
+                "symbol" => policy.require_symbol = true,
+                _ => {
+                    if let Some(min_length) = rule.strip_prefix("min=").and_then(|value| value.parse().ok()) {
+                        policy.min_length = min_length;
+                    }
+                }
+            }
+        }
+
+        policy
+    }
+
+    /// Check the length and character class rules, returning every violation
+    pub fn violations(&self, password: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            errors.push(format!("Password must be at least {} characters long.", self.min_length));
+        }
+        if length > MAX_PASSWORD_LENGTH {
+            errors.push(format!("Password must be at most {} characters long.", MAX_PASSWORD_LENGTH));
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            errors.push("Password must contain a lowercase letter.".to_string());
+        }
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            errors.push("Password must contain an uppercase letter.".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            errors.push("Password must contain a digit.".to_string());
+        }
+        if self.require_symbol && !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+            errors.push("Password must contain a symbol.".to_string());
+        }
+
+        errors
+    }
+}
+
+/// Look the password up in the local breached password corpus configured by
+/// BREACHED_PASSWORDS_DIR. The directory holds one file per SHA-1 prefix
+/// (`21BD1.txt`) with `SUFFIX:COUNT` lines, the layout of the Pwned Passwords
+/// range downloads, so only one small file is read per check.
+async fn is_breached(password: &str) -> bool {
+    let dir = match env::var("BREACHED_PASSWORDS_DIR") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => return false,
+    };
+
+    let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = digest.split_at(RANGE_PREFIX_LENGTH);
+    let path = dir.join(format!("{}.txt", prefix));
+    let suffix = suffix.to_string();
+
+    let result = web::block(move || match fs::read_to_string(&path) {
+        Ok(range) => Ok(range.lines().any(|line| {
+            line.split(':').next().is_some_and(|candidate| candidate.trim().eq_ignore_ascii_case(&suffix))
+        })),
+        // A missing range file means no breached password has this prefix
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    })
+    .await;
+
+    match result {
+        Ok(Ok(breached)) => breached,
+        Ok(Err(e)) => {
+            log::error!("Error reading breached password range: {}", e);
+            false
+        }
+        Err(e) => {
+            log::error!("Error checking breached passwords: {}", e);
+            false
+        }
+    }
+}
+
+/// Validate a new password for a user type; returns the messages for the `password` field
+pub async fn validate(password: &str, user_type: &UserType) -> Result<(), Vec<String>> {
+    let mut errors = PasswordPolicy::for_user_type(user_type).violations(password);
+
+    // Only worth the lookup once the password passes the local rules
+    if errors.is_empty() && is_breached(password).await {
+        errors.push("This password has appeared in a data breach. Please choose a different one.".to_string());
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_every_rule() {
+        let policy = PasswordPolicy::parse("min=12, lower,upper,digit,symbol");
+        assert_eq!(
+            policy,
+            PasswordPolicy {
+                min_length: 12,
+                require_lowercase: true,
+                require_uppercase: true,
+                require_digit: true,
+                require_symbol: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_ignores_unknown_rules_and_bad_lengths() {
+        let policy = PasswordPolicy::parse("min=abc,digits,,upper");
+        assert_eq!(policy.min_length, 8);
+        assert!(policy.require_uppercase);
+        assert!(!policy.require_digit);
+        assert!(!policy.require_lowercase);
+    }
+
+    #[test]
+    fn violations_lists_every_broken_rule() {
+        let policy = PasswordPolicy::parse("min=12,lower,upper,digit,symbol");
+        assert_eq!(
+            policy.violations("short"),
+            vec![
+                "Password must be at least 12 characters long.".to_string(),
+                "Password must contain an uppercase letter.".to_string(),
+                "Password must contain a digit.".to_string(),
+                "Password must contain a symbol.".to_string(),
+            ]
+        );
+        assert!(policy.violations("Long enough pass 1!").is_empty());
+    }
+
+    #[test]
+    fn violations_counts_characters_and_caps_the_length() {
+        let policy = PasswordPolicy::parse("min=8");
+        // Eight characters, sixteen bytes
+        assert!(policy.violations("ääääääää").is_empty());
+        assert_eq!(
+            policy.violations(&"a".repeat(MAX_PASSWORD_LENGTH + 1)),
+            vec![format!("Password must be at most {} characters long.", MAX_PASSWORD_LENGTH)]
+        );
+    }
+
+    #[test]
+    fn whitespace_is_not_a_symbol() {
+        let policy = PasswordPolicy::parse("min=1,symbol");
+        assert_eq!(policy.violations("no symbols here"), vec!["Password must contain a symbol.".to_string()]);
+        assert!(policy.violations("symbol-here").is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use std::env;
+use crate::auth::rate_limit::RateLimit;
+use crate::auth::token::{generate_token, hash_token};
+use crate::entities::{password_resets, users};
+use crate::mail::mailer::Mailer;
+
+const DEFAULT_PASSWORD_RESET_TTL_MINUTES: i64 = 60;
+
+lazy_static! {
+    static ref REQUESTS_PER_EMAIL: RateLimit =
+        RateLimit::from_env("PASSWORD_RESET_MAX_PER_EMAIL", 3, Duration::minutes(15));
+    static ref REQUESTS_PER_IP: RateLimit =
+        RateLimit::from_env("PASSWORD_RESET_MAX_PER_IP", 20, Duration::minutes(15));
+}
+
+/// Reasons a reset token cannot be redeemed
+#[derive(Debug)]
+pub enum ResetError {
+    /// The token is unknown, already used or expired
+    Invalid,
+    Db(DbErr),
+}
+
+impl From<DbErr> for ResetError {
+    fn from(err: DbErr) -> Self {
+        ResetError::Db(err)
+    }
+}
+
+/// Lifetime of a reset token, configurable through PASSWORD_RESET_TTL_MINUTES
+pub fn reset_token_ttl() -> Duration {
+    let minutes = env::var("PASSWORD_RESET_TTL_MINUTES")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_PASSWORD_RESET_TTL_MINUTES);
+    Duration::minutes(minutes)
+}
+
+/// Count a reset request against the IP and the email, or return the seconds until another is allowed.
+/// The limit applies whether or not the address belongs to an account.
+pub fn throttle(email: &str, ip: &str) -> Option<i64> {
+    REQUESTS_PER_IP
+        .hit(ip)
+        .or_else(|| REQUESTS_PER_EMAIL.hit(&email.trim().to_lowercase()))
+}
+
+/// Create a reset token for the user, invalidating any earlier unused ones
+pub async fn create(db: &DatabaseConnection, user_id: i32) -> Result<String, DbErr> {
+    password_resets::Entity::update_many()
+        .col_expr(password_resets::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(password_resets::Column::UserId.eq(user_id))
+        .filter(password_resets::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    let token = generate_token();
+    let reset = password_resets::ActiveModel {
+        user_id: Set(user_id),
+        token_hash: Set(hash_token(&token)),
+        expires_at: Set(Utc::now() + reset_token_ttl()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    password_resets::Entity::insert(reset).exec(db).await?;
+    Ok(token)
+}
+
+/// Mark a reset token as used and return the id of the user it belongs to
+pub async fn consume(db: &DatabaseConnection, token: &str) -> Result<i32, ResetError> {
+    let record = find_valid(db, token).await?;
+
+    // Guard against the same token being redeemed twice concurrently
+    let result = password_resets::Entity::update_many()
+        .col_expr(password_resets::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(password_resets::Column::Id.eq(record.id))
+        .filter(password_resets::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        return Err(ResetError::Invalid);
+    }
+
+    Ok(record.user_id)
+}
+
+/// Email a reset link pointing at the frontend
+pub async fn send_reset_email(user: &users::Model, token: &str) -> Result<(), String> {
+    let frontend_url = env::var("FRONTEND_URL").expect("FRONTEND_URL must be set");
+    let link = format!("{}/reset-password?token={}", frontend_url.trim_end_matches('/'), token);
+
+    let body = format!(
+        "Hi {},\n\nWe received a request to reset your password. Open the link below to choose a new one:\n\n{}\n\nThe link expires in {} minutes and can only be used once. If you did not request a reset, you can ignore this email.",
+        user.first_name, link, reset_token_ttl().num_minutes()
+    );
+
+    Mailer::send(&user.email, "Reset your proctodot password", body).await
+}
+
+/// Return the user a reset token belongs to without redeeming it
+pub async fn peek(db: &DatabaseConnection, token: &str) -> Result<i32, ResetError> {
+    Ok(find_valid(db, token).await?.user_id)
+}
+
+async fn find_valid(db: &DatabaseConnection, token: &str) -> Result<password_resets::Model, ResetError> {
+    let record = password_resets::Entity::find()
+        .filter(password_resets::Column::TokenHash.eq(hash_token(token)))
+        .one(db)
+        .await?
+        .ok_or(ResetError::Invalid)?;
+
+    if record.used_at.is_some() || record.expires_at <= Utc::now() {
+        return Err(ResetError::Invalid);
+    }
+
+    Ok(record)
+}
@@ -0,0 +1,54 @@
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::Sha256;
+use std::env;
+
+/// Separates the purpose token key from the access token key that SECRET_KEY signs directly
+const KEY_CONTEXT: &[u8] = b"proctodot purpose tokens";
+
+/// Keys for the short-lived tokens that never leave this service: MFA steps and the links
+/// for email verification, email changes and magic logins. They are always signed (HS256) with
+/// a key derived from SECRET_KEY, so it stays required even when access tokens use
+/// JWT_PRIVATE_KEY_PATH, and a purpose token never verifies as an access token.
+struct PurposeKeys {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+}
+
+lazy_static! {
+    static ref KEYS: PurposeKeys = PurposeKeys::from_env();
+}
+
+/// Load the secret now, so a missing SECRET_KEY stops the server at startup
+pub fn load() {
+    lazy_static::initialize(&KEYS);
+}
+
+impl PurposeKeys {
+    fn from_env() -> Self {
+        let secret = env::var("SECRET_KEY")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .expect("SECRET_KEY must be set");
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(KEY_CONTEXT);
+        let key = mac.finalize().into_bytes();
+        PurposeKeys {
+            encoding_key: EncodingKey::from_secret(&key),
+            decoding_key: DecodingKey::from_secret(&key),
+        }
+    }
+}
+
+/// Sign the claims of a purpose token
+pub fn sign<T: Serialize>(claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+    jsonwebtoken::encode(&Header::default(), claims, &KEYS.encoding_key)
+}
+
+/// Check the signature of a purpose token and return its claims
+pub fn decode<T: DeserializeOwned>(token: &str, validation: &Validation) -> Result<T, jsonwebtoken::errors::Error> {
+    jsonwebtoken::decode::<T>(token, &KEYS.decoding_key, validation).map(|data| data.claims)
+}
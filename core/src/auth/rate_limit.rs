@@ -0,0 +1,48 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+type Hits = HashMap<String, Vec<DateTime<Utc>>>;
+
+/// An in-memory request budget per key (an IP, an email, a user id) over a sliding window
+pub struct RateLimit {
+    max: usize,
+    window: Duration,
+    hits: Mutex<Hits>,
+}
+
+impl RateLimit {
+    pub fn new(max: usize, window: Duration) -> Self {
+        RateLimit { max, window, hits: Mutex::new(HashMap::new()) }
+    }
+
+    /// Read the budget from the `max_var` environment variable, falling back to `default_max`
+    pub fn from_env(max_var: &str, default_max: usize, window: Duration) -> Self {
+        let max = env::var(max_var)
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(default_max);
+        Self::new(max, window)
+    }
+
+    /// Count a request against the key, or return the seconds until another is allowed
+    pub fn hit(&self, key: &str) -> Option<i64> {
+        let now = Utc::now();
+        let window_start = now - self.window;
+        let mut hits = self.hits.lock().unwrap();
+
+        hits.retain(|_, times| {
+            times.retain(|time| *time > window_start);
+            !times.is_empty()
+        });
+
+        let times = hits.entry(key.to_string()).or_default();
+        if times.len() >= self.max {
+            return Some((times[0] - window_start).num_seconds().max(1));
+        }
+
+        times.push(now);
+        None
+    }
+}
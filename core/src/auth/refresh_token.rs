@@ -0,0 +1,149 @@
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use std::env;
+use uuid::Uuid;
+use crate::auth::token::{generate_token, hash_token};
+use crate::entities::refresh_tokens;
+
+const DEFAULT_REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+/// Reasons a refresh token cannot be exchanged for a new token pair
+#[derive(Debug)]
+pub enum RefreshError {
+    /// The token is unknown
+    Invalid,
+    /// The token was already used or revoked; its whole family has been revoked
+    Reused,
+    /// The token is past its expiry
+    Expired,
+    Db(DbErr),
+}
+
+impl From<DbErr> for RefreshError {
+    fn from(err: DbErr) -> Self {
+        RefreshError::Db(err)
+    }
+}
+
+/// Lifetime of a refresh token, configurable through REFRESH_TOKEN_TTL_DAYS
+pub fn refresh_token_ttl() -> Duration {
+    let days = env::var("REFRESH_TOKEN_TTL_DAYS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_REFRESH_TOKEN_TTL_DAYS);
+    Duration::days(days)
+}
+
+/// Issue a new refresh token, starting a new family unless one is given.
+/// Returns the raw token; only its hash is stored.
+pub async fn issue(
+    db: &DatabaseConnection,
+    user_id: i32,
+    family_id: Option<String>,
+) -> Result<String, DbErr> {
+    // Expired tokens can no longer be exchanged, so drop them on every write
+    prune_expired(db).await?;
+
+    let token = generate_token();
+    let new_token = refresh_tokens::ActiveModel {
+        user_id: Set(user_id),
+        family_id: Set(family_id.unwrap_or_else(|| Uuid::new_v4().to_string())),
+        token_hash: Set(hash_token(&token)),
+        expires_at: Set(Utc::now() + refresh_token_ttl()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    refresh_tokens::Entity::insert(new_token).exec(db).await?;
+    Ok(token)
+}
+
+/// Consume a refresh token so it can be replaced by a new one in the same family.
+/// Presenting a token that was already consumed revokes the whole family.
+pub async fn consume(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<refresh_tokens::Model, RefreshError> {
+    let record = find(db, token).await?.ok_or(RefreshError::Invalid)?;
+
+    if record.revoked_at.is_some() {
+        revoke_family(db, &record.family_id).await?;
+        return Err(RefreshError::Reused);
+    }
+
+    if record.expires_at <= Utc::now() {
+        return Err(RefreshError::Expired);
+    }
+
+    // Only one concurrent request may win the rotation; the loser is treated as reuse
+    let result = refresh_tokens::Entity::update_many()
+        .col_expr(refresh_tokens::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(refresh_tokens::Column::Id.eq(record.id))
+        .filter(refresh_tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+
+    if result.rows_affected == 0 {
+        revoke_family(db, &record.family_id).await?;
+        return Err(RefreshError::Reused);
+    }
+
+    Ok(record)
+}
+
+/// Find the token record belonging to a raw refresh token
+pub async fn find(
+    db: &DatabaseConnection,
+    token: &str,
+) -> Result<Option<refresh_tokens::Model>, DbErr> {
+    refresh_tokens::Entity::find()
+        .filter(refresh_tokens::Column::TokenHash.eq(hash_token(token)))
+        .one(db)
+        .await
+}
+
+/// Revoke every live token in a family
+pub async fn revoke_family(db: &DatabaseConnection, family_id: &str) -> Result<(), DbErr> {
+    refresh_tokens::Entity::update_many()
+        .col_expr(refresh_tokens::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(refresh_tokens::Column::FamilyId.eq(family_id))
+        .filter(refresh_tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Delete refresh tokens past their expiry, consumed or not
+pub async fn prune_expired(db: &DatabaseConnection) -> Result<(), DbErr> {
+    refresh_tokens::Entity::delete_many()
+        .filter(refresh_tokens::Column::ExpiresAt.lt(Utc::now()))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::PaginatorTrait;
+    use super::*;
+    use crate::enums::user_type::UserType;
+    use crate::test_support;
+
+    #[actix_web::test]
+    async fn issuing_drops_expired_tokens() {
+        let db = test_support::database().await;
+        let user = test_support::create_user(&db, UserType::Member, "refresh@example.com").await;
+        let expired = issue(&db, user.id, None).await.unwrap();
+        refresh_tokens::Entity::update_many()
+            .col_expr(refresh_tokens::Column::ExpiresAt, Expr::value(Utc::now() - Duration::minutes(1)))
+            .exec(&db)
+            .await
+            .unwrap();
+
+        let live = issue(&db, user.id, None).await.unwrap();
+        assert!(find(&db, &expired).await.unwrap().is_none());
+        assert!(find(&db, &live).await.unwrap().is_some());
+        assert_eq!(refresh_tokens::Entity::find().count(&db).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryFilter, Set};
+use crate::entities::revoked_tokens;
+use crate::http::middlewares::auth::Claims;
+
+/// Add an access token to the denylist until it would have expired anyway
+pub async fn revoke(db: &DatabaseConnection, claims: &Claims, user_id: i32) -> Result<(), DbErr> {
+    let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or_else(Utc::now);
+    revoke_jti(db, &claims.jti, user_id, expires_at).await
+}
+
+/// Denylist any signed token by its id until the given expiry
+pub async fn revoke_jti(
+    db: &DatabaseConnection,
+    jti: &str,
+    user_id: i32,
+    expires_at: DateTime<Utc>,
+) -> Result<(), DbErr> {
+    // Entries past their expiry can never match a valid token, so drop them on every write
+    prune_expired(db).await?;
+
+    let entry = revoked_tokens::ActiveModel {
+        jti: Set(jti.to_string()),
+        user_id: Set(user_id),
+        expires_at: Set(expires_at),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    };
+
+    revoked_tokens::Entity::insert(entry).exec(db).await?;
+    Ok(())
+}
+
+/// Check whether the token with the given id has been revoked
+pub async fn is_revoked(db: &DatabaseConnection, jti: &str) -> Result<bool, DbErr> {
+    let count = revoked_tokens::Entity::find()
+        .filter(revoked_tokens::Column::Jti.eq(jti))
+        .count(db)
+        .await?;
+    Ok(count > 0)
+}
+
+/// Delete denylist entries whose tokens have expired
+pub async fn prune_expired(db: &DatabaseConnection) -> Result<(), DbErr> {
+    revoked_tokens::Entity::delete_many()
+        .filter(revoked_tokens::Column::ExpiresAt.lt(Utc::now()))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use super::*;
+    use crate::enums::user_type::UserType;
+    use crate::test_support;
+
+    #[actix_web::test]
+    async fn revoking_drops_entries_past_their_expiry() {
+        let db = test_support::database().await;
+        let user = test_support::create_user(&db, UserType::Member, "revoked@example.com").await;
+        revoke_jti(&db, "expired", user.id, Utc::now() - Duration::minutes(1)).await.unwrap();
+        assert!(is_revoked(&db, "expired").await.unwrap());
+
+        revoke_jti(&db, "live", user.id, Utc::now() + Duration::minutes(15)).await.unwrap();
+        assert!(!is_revoked(&db, "expired").await.unwrap());
+        assert!(is_revoked(&db, "live").await.unwrap());
+    }
+}
@@ -0,0 +1,246 @@
+use actix_web::HttpRequest;
+use chrono::{Duration, Utc};
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, Set};
+use uuid::Uuid;
+use crate::auth::{login_throttle, refresh_token};
+use crate::entities::{refresh_tokens, sessions};
+
+/// How stale `last_seen_at` may get before a request rewrites it
+const LAST_SEEN_RESOLUTION_SECONDS: i64 = 60;
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// Where a request came from, recorded on the session it belongs to
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: String,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let user_agent = req
+            .headers()
+            .get("User-Agent")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        ClientInfo { user_agent, ip_address: login_throttle::client_ip(req) }
+    }
+}
+
+/// A short label such as "Firefox on Linux" for showing the session to its owner
+pub fn describe_device(user_agent: Option<&str>) -> String {
+    let user_agent = match user_agent {
+        Some(user_agent) if !user_agent.is_empty() => user_agent,
+        _ => return "Unknown device".to_string(),
+    };
+
+    // Order matters: Edge and Opera also claim to be Chrome, and Chrome claims to be Safari
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ]
+    .iter()
+    .find(|(marker, _)| user_agent.contains(marker))
+    .map(|(_, name)| *name);
+
+    let platform = [
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ]
+    .iter()
+    .find(|(marker, _)| user_agent.contains(marker))
+    .map(|(_, name)| *name);
+
+    match (browser, platform) {
+        (Some(browser), Some(platform)) => format!("{} on {}", browser, platform),
+        (Some(name), None) | (None, Some(name)) => name.to_string(),
+        (None, None) => "Unknown device".to_string(),
+    }
+}
+
+fn new_session(session_id: &str, user_id: i32, client: &ClientInfo) -> sessions::ActiveModel {
+    let now = Utc::now();
+    sessions::ActiveModel {
+        session_id: Set(session_id.to_string()),
+        user_id: Set(user_id),
+        device: Set(describe_device(client.user_agent.as_deref())),
+        user_agent: Set(client.user_agent.clone()),
+        ip_address: Set(client.ip_address.clone()),
+        last_seen_at: Set(now),
+        created_at: Set(now),
+        ..Default::default()
+    }
+}
+
+/// Record a new login. The returned session id goes into the `sid` claim and
+/// doubles as the family id of the session's refresh tokens.
+pub async fn start(db: &DatabaseConnection, user_id: i32, client: &ClientInfo) -> Result<sessions::Model, DbErr> {
+    new_session(&Uuid::new_v4().to_string(), user_id, client).insert(db).await
+}
+
+/// Continue a session when its refresh token is rotated, recording where the
+/// refresh came from. Families issued before sessions were tracked get a session here.
+pub async fn resume(
+    db: &DatabaseConnection,
+    session_id: &str,
+    user_id: i32,
+    client: &ClientInfo,
+) -> Result<(), DbErr> {
+    let exists = sessions::Entity::find()
+        .filter(sessions::Column::SessionId.eq(session_id))
+        .one(db)
+        .await?
+        .is_some();
+
+    if exists {
+        sessions::Entity::update_many()
+            .col_expr(sessions::Column::LastSeenAt, Expr::value(Utc::now()))
+            .col_expr(sessions::Column::IpAddress, Expr::value(client.ip_address.clone()))
+            .filter(sessions::Column::SessionId.eq(session_id))
+            .exec(db)
+            .await?;
+        return Ok(());
+    }
+
+    let session = new_session(session_id, user_id, client);
+    sessions::Entity::insert(session).exec(db).await?;
+    Ok(())
+}
+
+/// Check that a session is still live and note that it was just used.
+/// Returns false once the session has been revoked.
+pub async fn touch(db: &DatabaseConnection, session_id: &str, ip_address: &str) -> Result<bool, DbErr> {
+    let session = match sessions::Entity::find()
+        .filter(sessions::Column::SessionId.eq(session_id))
+        .one(db)
+        .await?
+    {
+        Some(session) if session.revoked_at.is_none() => session,
+        _ => return Ok(false),
+    };
+
+    // Writing on every request would turn each read into a write
+    if Utc::now() - session.last_seen_at > Duration::seconds(LAST_SEEN_RESOLUTION_SECONDS)
+        || session.ip_address != ip_address
+    {
+        sessions::Entity::update_many()
+            .col_expr(sessions::Column::LastSeenAt, Expr::value(Utc::now()))
+            .col_expr(sessions::Column::IpAddress, Expr::value(ip_address.to_string()))
+            .filter(sessions::Column::Id.eq(session.id))
+            .exec(db)
+            .await?;
+    }
+
+    Ok(true)
+}
+
+/// The live sessions of a user, most recently used first
+pub async fn list_for_user(db: &DatabaseConnection, user_id: i32) -> Result<Vec<sessions::Model>, DbErr> {
+    sessions::Entity::find()
+        .filter(sessions::Column::UserId.eq(user_id))
+        .filter(sessions::Column::RevokedAt.is_null())
+        .order_by_desc(sessions::Column::LastSeenAt)
+        .all(db)
+        .await
+}
+
+/// Revoke one of the user's sessions along with its refresh tokens.
+/// Returns false when the user has no live session with that id.
+pub async fn revoke(db: &DatabaseConnection, user_id: i32, id: i32) -> Result<bool, DbErr> {
+    let session = match sessions::Entity::find_by_id(id).one(db).await? {
+        Some(session) if session.user_id == user_id && session.revoked_at.is_none() => session,
+        _ => return Ok(false),
+    };
+
+    revoke_by_session_id(db, &session.session_id).await?;
+    Ok(true)
+}
+
+/// Revoke the session a refresh token family belongs to
+pub async fn revoke_by_session_id(db: &DatabaseConnection, session_id: &str) -> Result<(), DbErr> {
+    sessions::Entity::update_many()
+        .col_expr(sessions::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(sessions::Column::SessionId.eq(session_id))
+        .filter(sessions::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    refresh_token::revoke_family(db, session_id).await
+}
+
+/// Revoke every session of a user, optionally sparing the one making the request.
+/// Returns the number of sessions revoked.
+pub async fn revoke_all_for_user(
+    db: &DatabaseConnection,
+    user_id: i32,
+    except_session_id: Option<&str>,
+) -> Result<u64, DbErr> {
+    let mut sessions = sessions::Entity::update_many()
+        .col_expr(sessions::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(sessions::Column::UserId.eq(user_id))
+        .filter(sessions::Column::RevokedAt.is_null());
+    let mut tokens = refresh_tokens::Entity::update_many()
+        .col_expr(refresh_tokens::Column::RevokedAt, Expr::value(Utc::now()))
+        .filter(refresh_tokens::Column::UserId.eq(user_id))
+        .filter(refresh_tokens::Column::RevokedAt.is_null());
+
+    if let Some(session_id) = except_session_id {
+        sessions = sessions.filter(sessions::Column::SessionId.ne(session_id));
+        tokens = tokens.filter(refresh_tokens::Column::FamilyId.ne(session_id));
+    }
+
+    let result = sessions.exec(db).await?;
+    tokens.exec(db).await?;
+    Ok(result.rows_affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe_device;
+
+    #[test]
+    fn names_browser_and_platform() {
+        let cases = [
+            (
+                "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0",
+                "Firefox on Linux",
+            ),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36 Edg/130.0.0.0",
+                "Edge on Windows",
+            ),
+            (
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36 OPR/114.0.0.0",
+                "Opera on macOS",
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Mobile Safari/537.36",
+                "Chrome on Android",
+            ),
+            (
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 18_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.0 Mobile/15E148 Safari/604.1",
+                "Safari on iOS",
+            ),
+        ];
+
+        for (user_agent, expected) in cases {
+            assert_eq!(describe_device(Some(user_agent)), expected, "{}", user_agent);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_whatever_is_recognised() {
+        assert_eq!(describe_device(Some("curl/8.5.0")), "Unknown device");
+        assert_eq!(describe_device(Some("SomeApp/1.0 (Windows NT 10.0)")), "Windows");
+        assert_eq!(describe_device(Some("Firefox/131.0")), "Firefox");
+        assert_eq!(describe_device(Some("")), "Unknown device");
+        assert_eq!(describe_device(None), "Unknown device");
+    }
+}
@@ -0,0 +1,172 @@
+use data_encoding::{BASE64, BASE64URL_NOPAD};
+use ed25519_dalek::pkcs8::DecodePublicKey;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use lazy_static::lazy_static;
+use rsa::traits::PublicKeyParts;
+use std::env;
+use std::fs;
+
+/// A public key access tokens may be signed with, published in the JWKS
+pub struct VerificationKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub decoding_key: DecodingKey,
+    jwk: Jwk,
+}
+
+/// Keys used to sign and verify access tokens.
+///
+/// With JWT_PRIVATE_KEY_PATH unset, tokens are signed with the shared SECRET_KEY (HS256)
+/// and nothing is published. Otherwise the current key pair is read from
+/// JWT_PRIVATE_KEY_PATH/JWT_PUBLIC_KEY_PATH under JWT_SIGNING_KEY_ID, and retired public
+/// keys listed in JWT_VERIFICATION_KEYS (`kid=path,...`) stay valid until they are removed.
+pub struct KeyStore {
+    pub signing_kid: Option<String>,
+    pub signing_algorithm: Algorithm,
+    pub encoding_key: EncodingKey,
+    shared_secret: Option<DecodingKey>,
+    verification_keys: Vec<VerificationKey>,
+}
+
+lazy_static! {
+    pub static ref KEYS: KeyStore = KeyStore::from_env();
+}
+
+/// Load the keys now, so a broken key configuration stops the server at startup
+pub fn load() {
+    lazy_static::initialize(&KEYS);
+}
+
+impl KeyStore {
+    fn from_env() -> Self {
+        let private_key_path = match env::var("JWT_PRIVATE_KEY_PATH").ok().filter(|path| !path.is_empty()) {
+            Some(path) => path,
+            None => {
+                let secret = env::var("SECRET_KEY").expect("SECRET_KEY must be set");
+                return KeyStore {
+                    signing_kid: None,
+                    signing_algorithm: Algorithm::HS256,
+                    encoding_key: EncodingKey::from_secret(secret.as_ref()),
+                    shared_secret: Some(DecodingKey::from_secret(secret.as_ref())),
+                    verification_keys: Vec::new(),
+                };
+            }
+        };
+
+        let kid = env::var("JWT_SIGNING_KEY_ID").expect("JWT_SIGNING_KEY_ID must be set");
+        let public_key_path = env::var("JWT_PUBLIC_KEY_PATH").expect("JWT_PUBLIC_KEY_PATH must be set");
+
+        let current = load_verification_key(&kid, &public_key_path);
+        let private_pem = fs::read(&private_key_path).expect("Failed to read JWT_PRIVATE_KEY_PATH");
+        let encoding_key = match current.algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&private_pem),
+            _ => EncodingKey::from_rsa_pem(&private_pem),
+        }
+        .expect("JWT_PRIVATE_KEY_PATH does not match the type of JWT_PUBLIC_KEY_PATH");
+
+        let mut verification_keys = vec![];
+        let signing_algorithm = current.algorithm;
+        verification_keys.push(current);
+
+        for entry in env::var("JWT_VERIFICATION_KEYS").unwrap_or_default().split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (kid, path) = entry
+                .split_once('=')
+                .expect("JWT_VERIFICATION_KEYS entries must look like kid=path");
+            verification_keys.push(load_verification_key(kid.trim(), path.trim()));
+        }
+
+        KeyStore {
+            signing_kid: Some(kid),
+            signing_algorithm,
+            encoding_key,
+            shared_secret: None,
+            verification_keys,
+        }
+    }
+
+    /// Find the key for a token's `kid` header. Tokens without a `kid` are only
+    /// accepted while the server still signs with the shared secret.
+    pub fn verification_key(&self, kid: Option<&str>) -> Option<(&DecodingKey, Algorithm)> {
+        match kid {
+            Some(kid) => self
+                .verification_keys
+                .iter()
+                .find(|key| key.kid == kid)
+                .map(|key| (&key.decoding_key, key.algorithm)),
+            None => self.shared_secret.as_ref().map(|key| (key, Algorithm::HS256)),
+        }
+    }
+
+    /// Public keys in JWK Set format for services that verify our tokens
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.verification_keys.iter().map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
+
+/// Read a PEM encoded SubjectPublicKeyInfo; Ed25519 keys are used for EdDSA, RSA keys for RS256
+fn load_verification_key(kid: &str, path: &str) -> VerificationKey {
+    let pem = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read JWT public key {}: {}", path, e));
+    let der = pem_to_der(&pem).unwrap_or_else(|| panic!("JWT public key {} is not valid PEM", path));
+
+    let common = CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    };
+
+    if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_der(&der) {
+        let x = BASE64URL_NOPAD.encode(key.as_bytes());
+        return VerificationKey {
+            kid: kid.to_string(),
+            algorithm: Algorithm::EdDSA,
+            decoding_key: DecodingKey::from_ed_components(&x).expect("Invalid Ed25519 public key"),
+            jwk: Jwk {
+                common: CommonParameters { algorithm: Some(Algorithm::EdDSA), ..common },
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x,
+                }),
+            },
+        };
+    }
+
+    let key = rsa::RsaPublicKey::from_public_key_der(&der)
+        .unwrap_or_else(|_| panic!("JWT public key {} is neither an Ed25519 nor an RSA key", path));
+    let n = BASE64URL_NOPAD.encode(&key.n().to_bytes_be());
+    let e = BASE64URL_NOPAD.encode(&key.e().to_bytes_be());
+    VerificationKey {
+        kid: kid.to_string(),
+        algorithm: Algorithm::RS256,
+        decoding_key: DecodingKey::from_rsa_components(&n, &e).expect("Invalid RSA public key"),
+        jwk: Jwk {
+            common: CommonParameters { algorithm: Some(Algorithm::RS256), ..common },
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n,
+                e,
+            }),
+        },
+    }
+}
+
+/// Decode the base64 body of a single-block PEM file
+fn pem_to_der(pem: &str) -> Option<Vec<u8>> {
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+        .collect();
+    BASE64.decode(body.as_bytes()).ok()
+}
@@ -0,0 +1,110 @@
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use crate::auth::rate_limit::RateLimit;
+use crate::auth::token::generate_token;
+use crate::entities::{user_wallet, wallet_challenges};
+use crate::web3::wallet_handler::WalletHandler;
+
+const CHALLENGE_TTL_MINUTES: i64 = 5;
+
+lazy_static! {
+    static ref CHALLENGES_PER_IP: RateLimit =
+        RateLimit::from_env("WALLET_CHALLENGE_MAX_PER_IP", 30, Duration::minutes(5));
+}
+
+#[derive(Debug)]
+pub enum ChallengeError {
+    /// The nonce is unknown, used, expired or issued for another address
+    InvalidChallenge,
+    /// The signature does not match the challenge and address
+    InvalidSignature,
+    /// No user owns a wallet with this address
+    UnknownWallet,
+    Db(DbErr),
+}
+
+impl From<DbErr> for ChallengeError {
+    fn from(err: DbErr) -> Self {
+        ChallengeError::Db(err)
+    }
+}
+
+/// Count a challenge request against the client IP, or return the seconds until another is allowed
+pub fn throttle(ip: &str) -> Option<i64> {
+    CHALLENGES_PER_IP.hit(ip)
+}
+
+/// Create a single-use challenge for an address to sign
+pub async fn create(db: &DatabaseConnection, address: &str) -> Result<wallet_challenges::Model, DbErr> {
+    // Expired challenges can never be redeemed, so drop them on every write
+    wallet_challenges::Entity::delete_many()
+        .filter(wallet_challenges::Column::ExpiresAt.lt(Utc::now()))
+        .exec(db)
+        .await?;
+
+    let nonce = generate_token();
+    let issued_at = Utc::now();
+    let expires_at = issued_at + Duration::minutes(CHALLENGE_TTL_MINUTES);
+    let message = format!(
+        "proctodot wants you to sign in with your account:\n{}\n\nNonce: {}\nIssued At: {}\nExpiration Time: {}",
+        address,
+        nonce,
+        issued_at.to_rfc3339(),
+        expires_at.to_rfc3339()
+    );
+
+    let challenge = wallet_challenges::ActiveModel {
+        address: Set(address.to_string()),
+        nonce: Set(nonce),
+        message: Set(message),
+        expires_at: Set(expires_at),
+        created_at: Set(issued_at),
+        ..Default::default()
+    };
+
+    challenge.insert(db).await
+}
+
+/// Redeem a signed challenge and return the id of the user owning the wallet
+pub async fn verify(
+    db: &DatabaseConnection,
+    address: &str,
+    nonce: &str,
+    signature: &str,
+) -> Result<i32, ChallengeError> {
+    let challenge = wallet_challenges::Entity::find()
+        .filter(wallet_challenges::Column::Nonce.eq(nonce))
+        .filter(wallet_challenges::Column::Address.eq(address))
+        .one(db)
+        .await?
+        .ok_or(ChallengeError::InvalidChallenge)?;
+
+    if challenge.used_at.is_some() || challenge.expires_at <= Utc::now() {
+        return Err(ChallengeError::InvalidChallenge);
+    }
+
+    // Burn the nonce before checking the signature so it can never be tried twice
+    let result = wallet_challenges::Entity::update_many()
+        .col_expr(wallet_challenges::Column::UsedAt, Expr::value(Utc::now()))
+        .filter(wallet_challenges::Column::Id.eq(challenge.id))
+        .filter(wallet_challenges::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(ChallengeError::InvalidChallenge);
+    }
+
+    if !WalletHandler::verify_signature(address, &challenge.message, signature) {
+        return Err(ChallengeError::InvalidSignature);
+    }
+
+    let wallet = user_wallet::Entity::find_active()
+        .filter(user_wallet::Column::Address.eq(address))
+        .one(db)
+        .await?
+        .ok_or(ChallengeError::UnknownWallet)?;
+
+    Ok(wallet.user_id as i32)
+}
@@ -0,0 +1,54 @@
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use crate::auth::token::{generate_token, hash_token};
+
+const DEFAULT_WS_TICKET_TTL_SECONDS: i64 = 30;
+
+/// A ticket that lets one user open one room's WebSocket once
+struct Ticket {
+    user_id: i32,
+    room_id: String,
+    expires_at: DateTime<Utc>,
+}
+
+lazy_static! {
+    /// Keyed by the ticket's hash so a memory dump does not reveal usable tickets
+    static ref TICKETS: Mutex<HashMap<String, Ticket>> = Mutex::new(HashMap::new());
+}
+
+/// Lifetime of a ticket, configurable through WS_TICKET_TTL_SECONDS
+pub fn ticket_ttl() -> Duration {
+    let seconds = env::var("WS_TICKET_TTL_SECONDS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_WS_TICKET_TTL_SECONDS);
+    Duration::seconds(seconds)
+}
+
+/// Issue a ticket for the user to connect to the room
+pub fn issue(user_id: i32, room_id: &str) -> String {
+    let ticket = generate_token();
+    let mut tickets = TICKETS.lock().unwrap();
+    // Unused tickets are dropped whenever a new one is issued
+    tickets.retain(|_, ticket| ticket.expires_at > Utc::now());
+    tickets.insert(
+        hash_token(&ticket),
+        Ticket {
+            user_id,
+            room_id: room_id.to_string(),
+            expires_at: Utc::now() + ticket_ttl(),
+        },
+    );
+    ticket
+}
+
+/// Burn the ticket and return the user it was issued to, if it is valid for the room
+pub fn redeem(ticket: &str, room_id: &str) -> Option<i32> {
+    match TICKETS.lock().unwrap().remove(&hash_token(ticket)) {
+        Some(ticket) if ticket.room_id == room_id && ticket.expires_at > Utc::now() => Some(ticket.user_id),
+        _ => None,
+    }
+}
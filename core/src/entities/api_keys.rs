@@ -0,0 +1,44 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "api_keys")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub name: String,
+    /// The first characters of the key, so admins can tell keys apart
+    pub key_prefix: String,
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    /// Comma separated scopes such as `users:read,rooms:write`
+    pub scopes: String,
+    /// The admin who created the key; requests made with it act on their behalf
+    pub created_by: i32,
+    pub expires_at: Option<DateTimeUtc>,
+    pub last_used_at: Option<DateTimeUtc>,
+    pub revoked_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+}
+
+impl Model {
+    pub fn scope_list(&self) -> Vec<String> {
+        self.scopes
+            .split(',')
+            .filter(|scope| !scope.is_empty())
+            .map(|scope| scope.to_string())
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        unimplemented!()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
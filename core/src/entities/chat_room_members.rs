@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "chat_room_members")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub room_id: String,
+    pub user_id: i32,
+    /// The user type the member joined as: "member" or "proctor"
+    pub role: String,
+    pub first_joined_at: DateTimeUtc,
+    pub last_joined_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        unimplemented!()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,34 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "data_exports")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// The user whose data is exported
+    pub user_id: i32,
+    /// The user who asked for the export: the subject themselves or an admin
+    pub requested_by: i32,
+    /// "pending", "running", "completed" or "failed"
+    pub status: String,
+    /// Name of the archive inside DATA_EXPORT_DIR once the job has completed
+    #[serde(skip_serializing)]
+    pub file_name: Option<String>,
+    pub error: Option<String>,
+    pub completed_at: Option<DateTimeUtc>,
+    /// After this the archive is deleted and can no longer be downloaded
+    pub expires_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        unimplemented!()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
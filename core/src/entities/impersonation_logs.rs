@@ -0,0 +1,29 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "impersonation_logs")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub admin_id: i32,
+    /// The impersonated user
+    pub user_id: i32,
+    /// `jti` of the impersonation token, grouping the requests of one session
+    pub token_id: String,
+    pub method: String,
+    pub path: String,
+    pub status_code: i32,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        unimplemented!()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,31 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "lockout_events")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// The account targeted by the attempt that triggered the lockout, if it exists
+    pub user_id: Option<i32>,
+    pub email: String,
+    pub ip_address: String,
+    /// Which counter tripped: "email" or "ip"
+    pub reason: String,
+    pub failed_count: i32,
+    pub locked_until: DateTimeUtc,
+    pub unlocked_at: Option<DateTimeUtc>,
+    pub unlocked_by: Option<i32>,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        unimplemented!()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,4 +1,21 @@
 pub mod users;
 pub mod passwords;
 pub mod user_wallet;
-pub mod chat_rooms;
\ No newline at end of file
+pub mod chat_rooms;
+pub mod refresh_tokens;
+pub mod revoked_tokens;
+pub mod password_resets;
+pub mod user_mfa;
+pub mod mfa_recovery_codes;
+pub mod passkey_credentials;
+pub mod user_identities;
+pub mod wallet_challenges;
+pub mod login_attempts;
+pub mod lockout_events;
+pub mod invitations;
+pub mod api_keys;
+pub mod impersonation_logs;
+pub mod sessions;
+pub mod phone_changes;
+pub mod chat_room_members;
+pub mod data_exports;
\ No newline at end of file
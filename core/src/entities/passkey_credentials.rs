@@ -0,0 +1,38 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "passkey_credentials")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+
+    /// Hex encoded WebAuthn credential id
+    #[sea_orm(unique)]
+    pub credential_id: String,
+
+    pub name: String,
+
+    /// Serialized webauthn-rs passkey holding the public key and signature counter
+    #[serde(skip_serializing)]
+    #[sea_orm(column_type = "Text")]
+    pub passkey: String,
+
+    pub last_used_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+
+    #[serde(skip_serializing)]
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        unimplemented!()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
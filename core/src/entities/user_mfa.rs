@@ -0,0 +1,53 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::encryption::encryption::Encryptor;
+
+#[derive(Clone, Default, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "user_mfa")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+
+    #[sea_orm(unique)]
+    pub user_id: i32,
+
+    #[serde(skip_serializing)]
+    #[sea_orm(column_type = "Text", column_name = "secret")]
+    pub encrypted_secret: String,
+
+    pub enabled_at: Option<DateTimeUtc>,
+
+    /// Last TOTP time step accepted, used to reject replayed codes
+    #[serde(skip_serializing)]
+    pub last_used_step: Option<i64>,
+
+    #[serde(skip_serializing)]
+    pub created_at: DateTimeUtc,
+
+    #[serde(skip_serializing)]
+    pub updated_at: DateTimeUtc,
+}
+
+impl Model {
+    /// Encrypt the TOTP secret before saving to the database
+    pub fn set_secret(&mut self, secret: &str) -> Result<(), &'static str> {
+        self.encrypted_secret = Encryptor::encrypt_data(secret)?;
+        Ok(())
+    }
+
+    /// Decrypt the TOTP secret after retrieving it from the database
+    pub fn get_secret(&self) -> Result<String, &'static str> {
+        Encryptor::decrypt_data(&self.encrypted_secret)
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        unimplemented!()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
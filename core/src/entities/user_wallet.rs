@@ -67,4 +67,11 @@ impl RelationTrait for Relation {
     }
 }
 
-impl ActiveModelBehavior for crate::entities::user_wallet::ActiveModel {}
\ No newline at end of file
+impl ActiveModelBehavior for crate::entities::user_wallet::ActiveModel {}
+
+impl Entity {
+    /// Wallets that have not been soft deleted along with their user
+    pub fn find_active() -> Select<Entity> {
+        Self::find().filter(Column::DeletedAt.is_null())
+    }
+}
\ No newline at end of file
@@ -15,6 +15,9 @@ pub struct Model {
     pub last_name: String,
     pub phone: String,
     pub r#type: String,
+    pub verified_at: Option<DateTimeUtc>,
+    /// Set by an admin; disabled accounts cannot sign in or use existing tokens
+    pub disabled_at: Option<DateTimeUtc>,
 
     #[serde(skip_serializing)]
     pub created_at: DateTimeUtc,
@@ -35,10 +38,21 @@ impl RelationTrait for Relation {
 
 impl ActiveModelBehavior for ActiveModel {}
 
+impl Entity {
+    /// Users that have not been soft deleted; every lookup outside restore should start here
+    pub fn find_active() -> Select<Entity> {
+        Self::find().filter(Column::DeletedAt.is_null())
+    }
+
+    pub fn find_active_by_id(id: i32) -> Select<Entity> {
+        Self::find_by_id(id).filter(Column::DeletedAt.is_null())
+    }
+}
+
 #[async_trait]
 impl Placeable for Model {
     async fn fetch_by_id(db: &DatabaseConnection, id: i64) -> Result<Self, Box<dyn std::error::Error>> {
-        let user = Entity::find_by_id(id as i32)
+        let user = Entity::find_active_by_id(id as i32)
             .one(db)
             .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?; // Convert `DbErr` to `Box<dyn Error>`
@@ -1,12 +1,54 @@
 use actix_web::web;
+use crate::auth::{purpose_token, signing_keys};
+use crate::enums::user_type::UserType;
 use crate::http::middlewares::auth::AuthMiddleware;
-use crate::http::controllers::auth_controller::{login_user, logout_user, register_user};
-use crate::http::controllers::user_controller::{user_details, get_user_details_by_id};
-use crate::http::socket::websocket_controller::websocket_index;
+use crate::http::middlewares::deny_impersonation::DenyImpersonation;
+use crate::http::middlewares::require_role::RequireRole;
+use crate::http::middlewares::require_scope::RequireScope;
+use crate::http::controllers::api_key_controller::{create_api_key, list_api_keys, revoke_api_key};
+use crate::http::controllers::auth_controller::{change_password, forgot_password, login_user, logout_user, refresh_access_token, register_user, resend_verification_email, reset_password, verify_email};
+use crate::http::controllers::data_export_controller::{download_data_export, download_user_data_export, list_data_exports, list_user_data_exports, request_data_export, request_user_data_export};
+use crate::http::controllers::impersonation_controller::{impersonate_user, list_impersonation_logs};
+use crate::http::controllers::invitation_controller::{create_invitation, list_invitations, revoke_invitation};
+use crate::http::controllers::jwks_controller::jwks;
+use crate::http::controllers::lockout_controller::{list_lockout_events, unlock_login};
+use crate::http::controllers::magic_link_controller::{redeem_magic_link, send_magic_link};
+use crate::http::controllers::session_controller::{list_sessions, revoke_other_sessions, revoke_session, revoke_user_sessions};
+use crate::http::controllers::admin_user_controller::{change_user_role, delete_user, disable_user, enable_user, list_users, restore_user, update_user};
+use crate::http::controllers::mfa_controller::{activate_totp, disable_totp, login_mfa, login_mfa_activate, login_mfa_setup, regenerate_recovery_codes, setup_totp};
+use crate::http::controllers::oidc_controller::{oidc_authorize, oidc_callback};
+use crate::http::controllers::passkey_controller::{delete_passkey, finish_passkey_login, finish_passkey_registration, list_passkeys, start_passkey_login, start_passkey_registration};
+use crate::http::controllers::user_controller::{confirm_email_change, confirm_phone_change, get_user_details_by_id, update_user_details, user_details};
+use crate::http::controllers::wallet_auth_controller::{create_wallet_challenge, verify_wallet_challenge};
+use crate::http::socket::websocket_controller::{create_ws_ticket, websocket_index};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
+    // Fail at startup rather than on the first login when the JWT keys or SECRET_KEY are misconfigured
+    signing_keys::load();
+    purpose_token::load();
+
+    cfg.service(jwks);
+    // Ahead of the /api scope so the upgrade is authorised by its ticket, not AuthMiddleware
+    cfg.route("/api/ws/chat/{room_id}", web::get().to(websocket_index));
     cfg.service(register_user);
     cfg.service(login_user);
+    cfg.service(login_mfa);
+    cfg.service(login_mfa_setup);
+    cfg.service(login_mfa_activate);
+    cfg.service(start_passkey_login);
+    cfg.service(finish_passkey_login);
+    cfg.service(oidc_authorize);
+    cfg.service(oidc_callback);
+    cfg.service(send_magic_link);
+    cfg.service(redeem_magic_link);
+    cfg.service(create_wallet_challenge);
+    cfg.service(verify_wallet_challenge);
+    cfg.service(refresh_access_token);
+    cfg.service(verify_email);
+    cfg.service(resend_verification_email);
+    cfg.service(forgot_password);
+    cfg.service(reset_password);
+    cfg.service(confirm_email_change);
     cfg.service(
         web::scope("/api")
             .wrap(AuthMiddleware {})
@@ -14,8 +56,98 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             .service(logout_user)
             // User Controller apis
             .service(user_details)
-            .service(get_user_details_by_id)
-
-            .route("/ws/chat/{room_id}", web::get().to(websocket_index)),
+            .service(
+                web::resource("/user/details")
+                    .wrap(DenyImpersonation)
+                    .route(web::patch().to(update_user_details)),
+            )
+            .service(
+                web::resource("/user/password")
+                    .wrap(DenyImpersonation)
+                    .route(web::post().to(change_password)),
+            )
+            .service(
+                web::resource("/user/phone/confirm")
+                    .wrap(DenyImpersonation)
+                    .route(web::post().to(confirm_phone_change)),
+            )
+            .service(
+                web::resource("/user/{id}/details")
+                    .wrap(RequireRole(&[UserType::Proctor, UserType::Admin]))
+                    .wrap(RequireScope("users:read"))
+                    .route(web::get().to(get_user_details_by_id)),
+            )
+            // Personal data export apis
+            .service(
+                web::scope("/user/exports")
+                    .wrap(DenyImpersonation)
+                    .service(request_data_export)
+                    .service(list_data_exports)
+                    .service(download_data_export),
+            )
+            // Two-factor authentication apis
+            .service(
+                web::scope("/mfa")
+                    .wrap(DenyImpersonation)
+                    .service(setup_totp)
+                    .service(activate_totp)
+                    .service(disable_totp)
+                    .service(regenerate_recovery_codes),
+            )
+            // Passkey apis
+            .service(
+                web::scope("/passkeys")
+                    .wrap(DenyImpersonation)
+                    .service(start_passkey_registration)
+                    .service(finish_passkey_registration)
+                    .service(list_passkeys)
+                    .service(delete_passkey),
+            )
+            // Session apis
+            .service(
+                web::scope("/sessions")
+                    .wrap(DenyImpersonation)
+                    .service(list_sessions)
+                    .service(revoke_other_sessions)
+                    .service(revoke_session),
+            )
+            // Login throttling apis
+            .service(
+                web::resource("/lockout-events")
+                    .wrap(RequireRole(&[UserType::Proctor, UserType::Admin]))
+                    .wrap(RequireScope("security:read"))
+                    .route(web::get().to(list_lockout_events)),
+            )
+            // Admin apis
+            .service(
+                web::scope("/admin")
+                    .wrap(RequireRole(&[UserType::Admin]))
+                    .service(unlock_login)
+                    .service(create_invitation)
+                    .service(list_invitations)
+                    .service(revoke_invitation)
+                    .service(create_api_key)
+                    .service(list_api_keys)
+                    .service(revoke_api_key)
+                    .service(impersonate_user)
+                    .service(list_impersonation_logs)
+                    .service(revoke_user_sessions)
+                    .service(list_users)
+                    .service(update_user)
+                    .service(change_user_role)
+                    .service(disable_user)
+                    .service(enable_user)
+                    .service(delete_user)
+                    .service(restore_user)
+                    .service(request_user_data_export)
+                    .service(list_user_data_exports)
+                    .service(download_user_data_export),
+            )
+            // WebSocket apis
+            .service(
+                web::resource("/ws/tickets")
+                    .wrap(DenyImpersonation)
+                    .route(web::post().to(create_ws_ticket)),
+            ),
     );
 }
@@ -0,0 +1,390 @@
+use actix_web::{delete, get, patch, post, put, web, HttpResponse, Responder};
+use actix_web::web::Data;
+use chrono::{DateTime, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use sea_orm::sea_query::Condition;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, Order, QueryFilter, QueryOrder, QuerySelect,
+    Set, Value,
+};
+use serde::{Deserialize, Serialize};
+use crate::auth::account::{self, AccountError};
+use crate::entities::users;
+use crate::enums::user_type::UserType;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+use crate::http::validation::ProfileUpdate;
+
+const DEFAULT_PAGE_SIZE: u64 = 25;
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSort {
+    Id,
+    Email,
+    FirstName,
+    LastName,
+    CreatedAt,
+}
+
+impl UserSort {
+    fn column(&self) -> users::Column {
+        match self {
+            UserSort::Id => users::Column::Id,
+            UserSort::Email => users::Column::Email,
+            UserSort::FirstName => users::Column::FirstName,
+            UserSort::LastName => users::Column::LastName,
+            UserSort::CreatedAt => users::Column::CreatedAt,
+        }
+    }
+
+    /// The sort key of a row, as stored in a cursor
+    fn key(&self, user: &users::Model) -> serde_json::Value {
+        match self {
+            UserSort::Id => serde_json::json!(user.id),
+            UserSort::Email => serde_json::json!(user.email),
+            UserSort::FirstName => serde_json::json!(user.first_name),
+            UserSort::LastName => serde_json::json!(user.last_name),
+            UserSort::CreatedAt => serde_json::json!(user.created_at),
+        }
+    }
+
+    /// Turn a cursor's sort key back into a value of the column's type
+    fn value(&self, key: &serde_json::Value) -> Option<Value> {
+        match self {
+            UserSort::Id => key.as_i64().and_then(|id| i32::try_from(id).ok()).map(Value::from),
+            UserSort::CreatedAt => serde_json::from_value::<DateTime<Utc>>(key.clone()).ok().map(Value::from),
+            _ => key.as_str().map(|key| Value::from(key.to_string())),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+pub struct ListUsersQuery {
+    pub r#type: Option<String>,
+    pub chain: Option<String>,
+    /// Matched against email, first name and last name
+    pub search: Option<String>,
+    pub disabled: Option<bool>,
+    /// List soft deleted users instead of live ones
+    pub deleted: Option<bool>,
+    pub sort: Option<UserSort>,
+    pub order: Option<SortOrder>,
+    pub limit: Option<u64>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+/// Position after the last row of a page: its sort key, with the id breaking ties
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    key: serde_json::Value,
+    id: i32,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        BASE64URL_NOPAD.encode(serde_json::to_string(self).unwrap_or_default().as_bytes())
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let bytes = BASE64URL_NOPAD.decode(cursor.as_bytes()).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChangeRoleRequest {
+    pub r#type: String,
+}
+
+fn account_error_response(err: AccountError) -> HttpResponse {
+    match err {
+        AccountError::NotFound => Controller::not_found("User not found"),
+        AccountError::LastAdmin => Controller::conflict("At least one enabled admin account must remain."),
+        AccountError::EmailTaken => Controller::conflict("Another account now uses this email address."),
+        AccountError::Db(e) => {
+            log::error!("Error updating account: {}", e);
+            Controller::internal_server_error("Error updating user.")
+        }
+    }
+}
+
+#[get("/users")]
+pub async fn list_users(
+    query: web::Query<ListUsersQuery>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let sort = query.sort.unwrap_or(UserSort::CreatedAt);
+    let order = query.order.unwrap_or(SortOrder::Desc);
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let mut select = match query.deleted {
+        Some(true) => users::Entity::find().filter(users::Column::DeletedAt.is_not_null()),
+        _ => users::Entity::find_active(),
+    };
+
+    if let Some(user_type) = &query.r#type {
+        match user_type.parse::<UserType>() {
+            Ok(user_type) => select = select.filter(users::Column::Type.eq(user_type.to_string())),
+            Err(_) => return Controller::bad_request("Invalid user type. Must be one of 'member', 'proctor' or 'admin'."),
+        }
+    }
+    if let Some(chain) = &query.chain {
+        select = select.filter(users::Column::Chain.eq(chain.clone()));
+    }
+    if let Some(search) = query.search.as_deref().map(str::trim).filter(|search| !search.is_empty()) {
+        select = select.filter(
+            Condition::any()
+                .add(users::Column::Email.contains(search))
+                .add(users::Column::FirstName.contains(search))
+                .add(users::Column::LastName.contains(search)),
+        );
+    }
+    match query.disabled {
+        Some(true) => select = select.filter(users::Column::DisabledAt.is_not_null()),
+        Some(false) => select = select.filter(users::Column::DisabledAt.is_null()),
+        None => {}
+    }
+
+    // Keyset pagination: continue strictly after the last row of the previous page
+    if let Some(cursor) = &query.cursor {
+        let (cursor, key) = match Cursor::decode(cursor).and_then(|cursor| sort.value(&cursor.key).map(|key| (cursor, key))) {
+            Some(decoded) => decoded,
+            None => return Controller::bad_request("Invalid cursor."),
+        };
+        let column = sort.column();
+        select = select.filter(match order {
+            SortOrder::Asc => Condition::any()
+                .add(column.gt(key.clone()))
+                .add(Condition::all().add(column.eq(key)).add(users::Column::Id.gt(cursor.id))),
+            SortOrder::Desc => Condition::any()
+                .add(column.lt(key.clone()))
+                .add(Condition::all().add(column.eq(key)).add(users::Column::Id.lt(cursor.id))),
+        });
+    }
+
+    let direction = match order {
+        SortOrder::Asc => Order::Asc,
+        SortOrder::Desc => Order::Desc,
+    };
+    if sort != UserSort::Id {
+        select = select.order_by(sort.column(), direction.clone());
+    }
+
+    // One extra row tells whether there is another page
+    let mut users = match select
+        .order_by(users::Column::Id, direction)
+        .limit(limit + 1)
+        .all(db.get_ref())
+        .await
+    {
+        Ok(users) => users,
+        Err(_) => return Controller::internal_server_error("Error retrieving users"),
+    };
+
+    let next_cursor = if users.len() as u64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|last| Cursor { key: sort.key(last), id: last.id }.encode())
+    } else {
+        None
+    };
+
+    Controller::ok_with_data(
+        "Users retrieved successfully",
+        serde_json::json!({ "users": users, "next_cursor": next_cursor }),
+    )
+}
+
+#[patch("/users/{id}")]
+pub async fn update_user(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+    form: web::Json<ProfileUpdate>,
+) -> impl Responder {
+    let user = match Controller::get_user_by_id(path.into_inner(), db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    let mut errors = form.validate();
+
+    let email = form.email.as_deref().map(str::trim).filter(|email| *email != user.email);
+    if let Some(email) = email.filter(|_| !errors.contains_key("email")) {
+        match users::Entity::find_active().filter(users::Column::Email.eq(email)).one(db.get_ref()).await {
+            Ok(Some(_)) => {
+                errors.insert("email".to_string(), vec!["Email is already taken.".to_string()]);
+            }
+            Ok(None) => {}
+            Err(_) => return Controller::internal_server_error("Error updating user."),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Controller::validation_error("The given data was invalid.", errors);
+    }
+
+    let mut active_user: users::ActiveModel = user.into();
+    if let Some(email) = email {
+        // The new address has not been verified by its owner
+        active_user.email = Set(email.to_string());
+        active_user.verified_at = Set(None);
+    }
+    if let Some(first_name) = &form.first_name {
+        active_user.first_name = Set(first_name.trim().to_string());
+    }
+    if let Some(last_name) = &form.last_name {
+        active_user.last_name = Set(last_name.trim().to_string());
+    }
+    if let Some(phone) = &form.phone {
+        active_user.phone = Set(phone.trim().to_string());
+    }
+    active_user.updated_at = Set(Utc::now());
+
+    match active_user.update(db.get_ref()).await {
+        Ok(user) => Controller::ok_with_data("User updated successfully.", user),
+        Err(_) => Controller::internal_server_error("Error updating user."),
+    }
+}
+
+#[put("/users/{id}/role")]
+pub async fn change_user_role(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+    form: web::Json<ChangeRoleRequest>,
+) -> impl Responder {
+    let user_type = match form.r#type.parse::<UserType>() {
+        Ok(user_type) => user_type,
+        Err(_) => return Controller::bad_request("Invalid user type. Must be one of 'member', 'proctor' or 'admin'."),
+    };
+
+    match account::change_role(db.get_ref(), path.into_inner(), &user_type).await {
+        Ok(user) => Controller::ok_with_data("User role updated successfully.", user),
+        Err(err) => account_error_response(err),
+    }
+}
+
+#[post("/users/{id}/disable")]
+pub async fn disable_user(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+    if user_id == auth.user.id {
+        return Controller::bad_request("You cannot disable your own account.");
+    }
+
+    match account::disable(db.get_ref(), user_id).await {
+        Ok(user) => Controller::ok_with_data("User disabled successfully.", user),
+        Err(err) => account_error_response(err),
+    }
+}
+
+#[post("/users/{id}/enable")]
+pub async fn enable_user(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match account::enable(db.get_ref(), path.into_inner()).await {
+        Ok(user) => Controller::ok_with_data("User enabled successfully.", user),
+        Err(err) => account_error_response(err),
+    }
+}
+
+/// Soft delete: the account disappears everywhere but can be restored
+#[delete("/users/{id}")]
+pub async fn delete_user(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+    if user_id == auth.user.id {
+        return Controller::bad_request("You cannot delete your own account.");
+    }
+
+    match account::delete(db.get_ref(), user_id).await {
+        Ok(_) => Controller::ok_empty("User deleted successfully."),
+        Err(err) => account_error_response(err),
+    }
+}
+
+#[post("/users/{id}/restore")]
+pub async fn restore_user(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match account::restore(db.get_ref(), path.into_inner()).await {
+        Ok(user) => Controller::ok_with_data("User restored successfully.", user),
+        Err(err) => account_error_response(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> users::Model {
+        let created_at = "2026-10-18T12:34:56Z".parse().unwrap();
+        users::Model {
+            id: 42,
+            chain: "polkadot".to_string(),
+            email: "ada@example.com".to_string(),
+            first_name: "Ada".to_string(),
+            last_name: "Lovelace".to_string(),
+            phone: String::new(),
+            r#type: UserType::Member.to_string(),
+            verified_at: None,
+            disabled_at: None,
+            created_at,
+            updated_at: created_at,
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn cursor_round_trips_for_every_sort() {
+        let user = user();
+        let expected = [
+            (UserSort::Id, Value::from(42)),
+            (UserSort::Email, Value::from("ada@example.com".to_string())),
+            (UserSort::FirstName, Value::from("Ada".to_string())),
+            (UserSort::LastName, Value::from("Lovelace".to_string())),
+            (UserSort::CreatedAt, Value::from(user.created_at)),
+        ];
+
+        for (sort, value) in expected {
+            let encoded = Cursor { key: sort.key(&user), id: user.id }.encode();
+            let cursor = Cursor::decode(&encoded).expect("cursor decodes");
+            assert_eq!(cursor.id, 42);
+            assert_eq!(sort.value(&cursor.key), Some(value));
+        }
+    }
+
+    #[test]
+    fn decode_rejects_malformed_cursors() {
+        assert!(Cursor::decode("").is_none());
+        assert!(Cursor::decode("not base64!").is_none());
+        assert!(Cursor::decode(&BASE64URL_NOPAD.encode(b"{\"key\":1}")).is_none());
+    }
+
+    #[test]
+    fn cursor_from_another_sort_is_rejected() {
+        let user = user();
+        let email_key = UserSort::Email.key(&user);
+        assert!(UserSort::Id.value(&email_key).is_none());
+        assert!(UserSort::CreatedAt.value(&email_key).is_none());
+        assert!(UserSort::Email.value(&UserSort::Id.key(&user)).is_none());
+        // Ids beyond i32 cannot come from a real row
+        assert!(UserSort::Id.value(&serde_json::json!(i64::MAX)).is_none());
+    }
+}
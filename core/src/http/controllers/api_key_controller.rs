@@ -0,0 +1,72 @@
+use actix_web::{delete, get, post, web, Responder};
+use actix_web::web::Data;
+use chrono::{DateTime, Utc};
+use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder};
+use serde::Deserialize;
+use crate::auth::api_key;
+use crate::entities::api_keys;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+
+#[derive(Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[post("/api-keys")]
+pub async fn create_api_key(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: web::Json<CreateApiKeyRequest>,
+) -> impl Responder {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Controller::bad_request("API key name is required.");
+    }
+    if form.scopes.is_empty() {
+        return Controller::bad_request("At least one scope is required.");
+    }
+    if let Some(scope) = form.scopes.iter().find(|scope| !api_key::is_valid_scope(scope)) {
+        return Controller::bad_request(&format!("Invalid scope '{}'. Scopes look like 'users:read'.", scope));
+    }
+    if form.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Controller::bad_request("Expiry must be in the future.");
+    }
+
+    match api_key::create(db.get_ref(), name, &form.scopes, form.expires_at, auth.user.id).await {
+        // The key is only ever shown here
+        Ok((record, key)) => Controller::created(
+            "API key created successfully.",
+            serde_json::json!({ "api_key": key, "key": record }),
+        ),
+        Err(_) => Controller::internal_server_error("Error creating API key."),
+    }
+}
+
+#[get("/api-keys")]
+pub async fn list_api_keys(
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match api_keys::Entity::find()
+        .order_by_desc(api_keys::Column::CreatedAt)
+        .all(db.get_ref())
+        .await
+    {
+        Ok(keys) => Controller::ok_with_data("API keys retrieved successfully", keys),
+        Err(_) => Controller::internal_server_error("Error retrieving API keys"),
+    }
+}
+
+#[delete("/api-keys/{id}")]
+pub async fn revoke_api_key(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match api_key::revoke(db.get_ref(), path.into_inner()).await {
+        Ok(true) => Controller::ok_empty("API key revoked successfully."),
+        Ok(false) => Controller::not_found("API key not found"),
+        Err(_) => Controller::internal_server_error("Error revoking API key"),
+    }
+}
@@ -1,21 +1,24 @@
 use crate::enums::user_type::UserType;
 use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
-use sea_orm::{DatabaseConnection, DbErr, EntityTrait, InsertResult, QueryFilter, Set};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, DbErr, EntityTrait, InsertResult, QueryFilter, Set};
 use serde::{Deserialize};
-use chrono::{Duration, Utc};
+use chrono::Utc;
+use std::str::FromStr;
 use sea_orm::ColumnTrait;
-use argon2::{Argon2, PasswordHasher};
-use argon2::password_hash::{SaltString, rand_core::OsRng, PasswordHash, PasswordVerifier};
-use jsonwebtoken::{encode, Header, EncodingKey};
-use crate::http::response::ApiResponse;
-use crate::http::middlewares::auth::Claims;
-use std::env;
+use crate::http::response::{ApiResponse, FieldErrors};
 use actix_web::web::Data;
 use crate::entities::{passwords, user_wallet, users};
 use crate::entities::user_wallet::ActiveModel;
 use crate::entities::users::Model;
 use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
 use crate::web3::wallet_handler;
+use crate::auth::jwt;
+use crate::auth::refresh_token::{self, RefreshError};
+use crate::auth::invitation::{self, InvitationError};
+use crate::auth::{email_verification, login_throttle, mfa, password, password_policy, password_reset, revoked_token, session};
+use crate::auth::password_reset::ResetError;
+use crate::auth::session::ClientInfo;
 
 #[derive(Deserialize)]
 pub struct RegisterUserRequest {
@@ -25,6 +28,8 @@ pub struct RegisterUserRequest {
     pub password: String,
     pub phone: String,
     pub chain: String,
+    /// Required when registering as a proctor or admin
+    pub invite_code: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -33,13 +38,52 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
 #[derive(Deserialize)]
 pub struct VerifyRequest {
     pub confirm_password: String,
     pub password: String,
 }
 
-async fn generate_wallet(db: Data<DatabaseConnection>, user_id: i32) -> Result<InsertResult<ActiveModel>, DbErr> {
+#[derive(Deserialize)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    #[serde(flatten)]
+    pub passwords: VerifyRequest,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    #[serde(flatten)]
+    pub passwords: VerifyRequest,
+}
+
+pub(crate) async fn generate_wallet(db: Data<DatabaseConnection>, user_id: i32) -> Result<InsertResult<ActiveModel>, DbErr> {
     let wallet_info = wallet_handler::WalletHandler::generate_wallet();
     // Store the encrypted private key and wallet info
     let mut new_wallet = user_wallet::Model {
@@ -59,62 +103,67 @@ async fn generate_wallet(db: Data<DatabaseConnection>, user_id: i32) -> Result<I
     wallet_result
 }
 
-fn generate_user_response_with_token(user: Model) -> serde_json::Value {
-    let expiration = Utc::now() + Duration::hours(24);
-    let claims = Claims {
-        sub: user.id.to_string(),
-        exp: expiration.timestamp() as usize,
-    };
+/// Start a session for the requesting device, then issue an access token plus a
+/// refresh token in the session's family and bundle them with the user
+pub(crate) async fn generate_user_response_with_token(
+    db: &DatabaseConnection,
+    user: Model,
+    req: &HttpRequest,
+) -> Result<serde_json::Value, DbErr> {
+    let session = session::start(db, user.id, &ClientInfo::from_request(req)).await?;
+    generate_token_pair(db, user, session.session_id).await
+}
+
+/// Finish a login whose first factor has been checked: refuse disabled accounts, hand out the
+/// MFA challenge when 2FA is enabled or required, and only otherwise start a session
+pub(crate) async fn finish_login(db: &DatabaseConnection, user: Model, req: &HttpRequest) -> HttpResponse {
+    if let Some(err_response) = Controller::reject_disabled(&user) {
+        return err_response;
+    }
+
+    // Users with 2FA get an intermediate token instead of a session, and their failure
+    // count is only cleared once the second factor has been passed as well
+    match mfa::login_challenge(db, &user).await {
+        Ok(Some(challenge)) => return Controller::ok_with_data("Two-factor authentication required.", challenge),
+        Ok(None) => {}
+        Err(_) => return Controller::internal_server_error("Error logging in."),
+    }
+
+    if login_throttle::record_success(db, &user.email).await.is_err() {
+        return Controller::internal_server_error("Error logging in.");
+    }
+
+    match generate_user_response_with_token(db, user, req).await {
+        Ok(response_data) => Controller::ok_with_data("Login successful", response_data),
+        Err(_) => Controller::internal_server_error("Error generating token."),
+    }
+}
 
-    // Load the secret key from the .env file
-    let secret = env::var("SECRET_KEY").expect("SECRET_KEY must be set");
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(
-            secret.as_ref()
-        )
-    ).unwrap();
+async fn generate_token_pair(
+    db: &DatabaseConnection,
+    user: Model,
+    session_id: String,
+) -> Result<serde_json::Value, DbErr> {
+    let token = jwt::encode_access_token(user.id, &session_id)
+        .map_err(|e| DbErr::Custom(format!("Failed to sign access token: {}", e)))?;
+    let refresh_token = refresh_token::issue(db, user.id, Some(session_id)).await?;
 
     let result = serde_json::json!({
         "token": token,
+        "refresh_token": refresh_token,
+        "expires_in": jwt::access_token_ttl().num_seconds(),
         "user": user
     });
 
-    result
+    Ok(result)
 }
 
-#[post("/register/{type}")]
-pub async fn register_user(
-    db: Data<DatabaseConnection>,
-    form: web::Json<RegisterUserRequest>,
-    user_type: web::Path<String>,
-) -> impl Responder {
-    // Parse the user type from the path parameter
-    let user_type = match user_type.into_inner().parse::<UserType>() {
-        Ok(user_type) => user_type,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
-                success: false,
-                message: "Invalid user type. Must be either 'member' or 'proctor'.".to_string(),
-                data: None,
-            });
-        }
-    };
-
-    // Check if the email already exists
-    if let Ok(Some(_)) = users::Entity::find()
-        .filter(users::Column::Email.eq(form.email.clone()))
-        .one(db.get_ref())
-        .await
-    {
-        return HttpResponse::BadRequest().json(ApiResponse::<()> {
-            success: false,
-            message: "Email is already taken.".to_string(),
-            data: None,
-        });
-    }
-
+/// Create the user with their password and wallet, returning the response to send if any step fails
+async fn create_account(
+    db: &Data<DatabaseConnection>,
+    form: &RegisterUserRequest,
+    user_type: &UserType,
+) -> Result<users::Model, HttpResponse> {
     // Create a new user
     let new_user = users::ActiveModel {
         r#type: Set(user_type.to_string()),
@@ -129,23 +178,21 @@ pub async fn register_user(
     };
 
     // Insert the new user into the database
-    let result = users::Entity::insert(new_user).exec(db.get_ref()).await;
-
-    let insert_result = match result {
+    let insert_result = match users::Entity::insert(new_user).exec(db.get_ref()).await {
         Ok(insert_result) => insert_result,
         Err(DbErr::Exec(err)) => {
-            return HttpResponse::BadRequest().json(ApiResponse::<()> {
+            return Err(HttpResponse::BadRequest().json(ApiResponse::<()> {
                 success: false,
                 message: err.to_string(),
                 data: None,
-            });
+            }));
         }
         Err(_) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
+            return Err(HttpResponse::InternalServerError().json(ApiResponse::<()> {
                 success: false,
                 message: "Error registering user.".to_string(),
                 data: None,
-            });
+            }));
         }
     };
 
@@ -154,30 +201,26 @@ pub async fn register_user(
     let user = match users::Entity::find_by_id(user_id).one(db.get_ref()).await {
         Ok(Some(user)) => user,
         Ok(None) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
+            return Err(HttpResponse::InternalServerError().json(ApiResponse::<()> {
                 success: false,
                 message: "Error retrieving newly created user.".to_string(),
                 data: None,
-            });
+            }));
         }
         Err(_) => {
-            return HttpResponse::InternalServerError().json(ApiResponse::<()> {
+            return Err(HttpResponse::InternalServerError().json(ApiResponse::<()> {
                 success: false,
                 message: "Error retrieving user.".to_string(),
                 data: None,
-            });
+            }));
         }
     };
 
-    // Generate a salt
-    let salt = SaltString::generate(&mut OsRng);
-
     // Hash the password using Argon2
-    let argon2 = Argon2::default();
-    let password_hash = argon2
-        .hash_password(form.password.as_bytes(), &salt)
-        .unwrap()
-        .to_string();
+    let password_hash = match password::hash_password(&form.password).await {
+        Ok(password_hash) => password_hash,
+        Err(_) => return Err(Controller::internal_server_error("Error hashing password.")),
+    };
 
     // Insert the hashed password into the passwords table
     let new_password = passwords::ActiveModel {
@@ -192,83 +235,186 @@ pub async fn register_user(
         .await;
 
     if password_result.is_err() {
-        return HttpResponse::InternalServerError().json(ApiResponse::<()> {
+        return Err(HttpResponse::InternalServerError().json(ApiResponse::<()> {
             success: false,
             message: "Error saving password.".to_string(),
             data: None,
-        });
+        }));
     }
 
     // Generate the user wallet
-    if let Err(_) = generate_wallet(db.clone(), user_id).await {
-        return HttpResponse::InternalServerError().json(ApiResponse::<()> {
+    if generate_wallet(db.clone(), user_id).await.is_err() {
+        return Err(HttpResponse::InternalServerError().json(ApiResponse::<()> {
             success: false,
             message: "Error saving wallet information.".to_string(),
             data: None,
+        }));
+    }
+
+    Ok(user)
+}
+
+#[post("/register/{type}")]
+pub async fn register_user(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<RegisterUserRequest>,
+    user_type: web::Path<String>,
+) -> impl Responder {
+    // Parse the user type from the path parameter
+    let user_type = match user_type.into_inner().parse::<UserType>() {
+        Ok(user_type) => user_type,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(ApiResponse::<()> {
+                success: false,
+                message: "Invalid user type. Must be one of 'member', 'proctor' or 'admin'.".to_string(),
+                data: None,
+            });
+        }
+    };
+
+    if let Err(errors) = password_policy::validate(&form.password, &user_type).await {
+        return password_rejected(errors);
+    }
+
+    // Check if the email already exists
+    if let Ok(Some(_)) = users::Entity::find_active()
+        .filter(users::Column::Email.eq(form.email.clone()))
+        .one(db.get_ref())
+        .await
+    {
+        return HttpResponse::BadRequest().json(ApiResponse::<()> {
+            success: false,
+            message: "Email is already taken.".to_string(),
+            data: None,
         });
     }
 
-    let response_data = generate_user_response_with_token(user.clone());
+    // Proctor and admin accounts can only be created through an invite
+    let invitation = if invitation::required_for(&user_type) {
+        let invite_code = match &form.invite_code {
+            Some(invite_code) => invite_code,
+            None => return Controller::forbidden("An invite code is required to register as this user type."),
+        };
+
+        match invitation::claim(db.get_ref(), invite_code, &user_type, &form.email).await {
+            Ok(invitation) => Some(invitation),
+            Err(InvitationError::Invalid) => return Controller::forbidden("Invalid or expired invite code."),
+            Err(InvitationError::EmailMismatch) => {
+                return Controller::forbidden("This invite was issued for a different email address.")
+            }
+            Err(InvitationError::Db(e)) => {
+                log::error!("Error redeeming invite: {}", e);
+                return Controller::internal_server_error("Error registering user.");
+            }
+        }
+    } else {
+        None
+    };
+
+    let user = match create_account(&db, &form, &user_type).await {
+        Ok(user) => user,
+        Err(err_response) => {
+            // Hand the invite back so it can be used for another attempt
+            if let Some(invitation) = &invitation {
+                if let Err(e) = invitation::release(db.get_ref(), invitation.id).await {
+                    log::error!("Error releasing invite {}: {}", invitation.id, e);
+                }
+            }
+            return err_response;
+        }
+    };
+
+    if let Some(invitation) = &invitation {
+        if let Err(e) = invitation::complete(db.get_ref(), invitation.id, user.id).await {
+            log::error!("Error recording redeemed invite {}: {}", invitation.id, e);
+        }
+    }
+
+    // A delivery failure should not undo the registration; the link can be requested again
+    if let Err(e) = email_verification::send_verification_email(&user).await {
+        log::error!("Error sending verification email to user {}: {}", user.id, e);
+    }
+
+    // Invited proctors and admins get the enrolment challenge before their first session
+    finish_login(db.get_ref(), user, &req).await
+}
+
+/// Record a failed login against the email and client IP and build the generic error response
+async fn login_failed(
+    db: &DatabaseConnection,
+    email: &str,
+    ip: &str,
+    user_id: Option<i32>,
+) -> HttpResponse {
+    if let Err(e) = login_throttle::record_failure(db, email, ip, user_id).await {
+        log::error!("Error recording failed login attempt: {}", e);
+    }
 
-    HttpResponse::Ok().json(ApiResponse {
-        success: true,
-        message: "User registered successfully.".to_string(),
-        data: Some(response_data),
+    HttpResponse::Unauthorized().json(ApiResponse::<()> {
+        success: false,
+        message: "Invalid username or password.".to_string(),
+        data: None,
     })
 }
 
 #[post("/login")]
 pub async fn login_user(
+    req: HttpRequest,
     db: Data<DatabaseConnection>,
     form: web::Json<LoginRequest>,
 ) -> impl Responder {
+    // Refuse to even check the password while the email or the client IP is throttled
+    let ip = login_throttle::client_ip(&req);
+    match login_throttle::check(db.get_ref(), &form.email, &ip).await {
+        Ok(Some(retry_after)) => {
+            return Controller::too_many_requests("Too many failed login attempts. Please try again later.", retry_after)
+        }
+        Ok(None) => {}
+        Err(_) => return Controller::internal_server_error("Error logging in."),
+    }
 
-    let user = users::Entity::find()
+    let user = users::Entity::find_active()
         .filter(users::Column::Email.eq(form.email.clone()))
         .one(db.get_ref())
         .await;
 
     match user {
         Ok(Some(user)) => {
+            // Older hashes are kept as history; only the newest one is the password
+            let stored_password = match password::current(db.get_ref(), user.id).await {
+                Ok(stored_password) => stored_password,
+                Err(_) => return Controller::internal_server_error("Error logging in."),
+            };
+            let stored_hash = stored_password.as_ref().map(|stored| stored.password_hash.as_str());
+
+            match password::verify_password(&form.password, stored_hash).await {
+                Ok(true) => {}
+                Ok(false) => return login_failed(db.get_ref(), &form.email, &ip, Some(user.id)).await,
+                Err(_) => return Controller::internal_server_error("Error logging in."),
+            }
 
-            // Fetch the hashed password from the passwords table
-            let stored_password = passwords::Entity::find()
-                .filter(passwords::Column::UserId.eq(user.id))
-                .one(db.get_ref())
-                .await;
-
-            match stored_password {
-                Ok(Some(stored_password)) => {
-                    let argon2 = Argon2::default();
-                    let parsed_hash = PasswordHash::new(&stored_password.password_hash).unwrap();
-
-                    if argon2.verify_password(form.password.as_bytes(), &parsed_hash).is_ok() {
-                        let response_data = generate_user_response_with_token(user.clone());
-                        HttpResponse::Ok().json(ApiResponse {
-                            success: true,
-                            message: "Login successful".to_string(),
-                            data: Some(response_data),
-                        })
-                    } else {
-                        HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                            success: false,
-                            message: "Invalid username or password.".to_string(),
-                            data: None,
-                        })
+            // Upgrade hashes made with older Argon2 parameters while the plain password is at hand
+            if let Some(stored_password) = stored_password.filter(|stored| password::needs_rehash(&stored.password_hash)) {
+                match password::hash_password(&form.password).await {
+                    Ok(password_hash) => {
+                        if let Err(e) = password::update_hash(db.get_ref(), stored_password.id, password_hash).await {
+                            log::error!("Error rehashing password of user {}: {}", user.id, e);
+                        }
                     }
+                    Err(e) => log::error!("Error rehashing password of user {}: {}", user.id, e),
                 }
-                _ => HttpResponse::Unauthorized().json(ApiResponse::<()> {
-                    success: false,
-                    message: "Invalid username or password.".to_string(),
-                    data: None,
-                }),
             }
+
+            finish_login(db.get_ref(), user, &req).await
+        }
+        Ok(None) => {
+            // Spend as long as a real check would, so the timing does not reveal unknown emails
+            if password::verify_password(&form.password, None).await.is_err() {
+                return Controller::internal_server_error("Error logging in.");
+            }
+            login_failed(db.get_ref(), &form.email, &ip, None).await
         }
-        Ok(None) => HttpResponse::Unauthorized().json(ApiResponse::<()> {
-            success: false,
-            message: "Invalid username or password.".to_string(),
-            data: None,
-        }),
         Err(_) => HttpResponse::InternalServerError().json(ApiResponse::<()> {
             success: false,
             message: "Error logging in.".to_string(),
@@ -277,19 +423,560 @@ pub async fn login_user(
     }
 }
 
+#[post("/verify-email")]
+pub async fn verify_email(
+    db: Data<DatabaseConnection>,
+    form: web::Json<VerifyEmailRequest>,
+) -> impl Responder {
+    let (user_id, email) = match email_verification::verify_token(&form.token) {
+        Ok(verified) => verified,
+        Err(message) => return Controller::bad_request(message),
+    };
+
+    let user = match Controller::get_user_by_id(user_id, db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    // The link is only valid for the address it was sent to
+    if user.email != email {
+        return Controller::bad_request("Invalid or expired verification link");
+    }
+
+    if user.verified_at.is_some() {
+        return Controller::ok_with_data("Email address already verified.", user);
+    }
+
+    let mut active_user: users::ActiveModel = user.into();
+    active_user.verified_at = Set(Some(Utc::now()));
+    active_user.updated_at = Set(Utc::now());
+
+    match active_user.update(db.get_ref()).await {
+        Ok(user) => Controller::ok_with_data("Email address verified successfully.", user),
+        Err(_) => Controller::internal_server_error("Error verifying email address."),
+    }
+}
+
+#[post("/verify-email/resend")]
+pub async fn resend_verification_email(
+    db: Data<DatabaseConnection>,
+    form: web::Json<ResendVerificationRequest>,
+) -> impl Responder {
+    let user = users::Entity::find_active()
+        .filter(users::Column::Email.eq(form.email.clone()))
+        .one(db.get_ref())
+        .await;
+
+    match user {
+        Ok(Some(user)) if user.verified_at.is_none() => {
+            if let Err(e) = email_verification::send_verification_email(&user).await {
+                log::error!("Error sending verification email to user {}: {}", user.id, e);
+            }
+        }
+        Ok(_) => {}
+        Err(_) => return Controller::internal_server_error("Error sending verification email."),
+    }
+
+    // Respond the same way whether or not the address exists
+    Controller::ok_empty("If the account exists and is unverified, a verification email has been sent.")
+}
+
+#[post("/password/forgot")]
+pub async fn forgot_password(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<ForgotPasswordRequest>,
+) -> impl Responder {
+    if let Some(retry_after) = password_reset::throttle(&form.email, &login_throttle::client_ip(&req)) {
+        return Controller::too_many_requests("Too many password reset requests. Please try again later.", retry_after);
+    }
+
+    // Look the account up and mail it off the request path, so the response time
+    // does not reveal whether the address is registered
+    let db = db.get_ref().clone();
+    let email = form.into_inner().email;
+    actix_web::rt::spawn(async move {
+        let user = match users::Entity::find_active()
+            .filter(users::Column::Email.eq(email))
+            .one(&db)
+            .await
+        {
+            Ok(Some(user)) => user,
+            Ok(None) => return,
+            Err(e) => {
+                log::error!("Error looking up user for password reset: {}", e);
+                return;
+            }
+        };
+
+        let token = match password_reset::create(&db, user.id).await {
+            Ok(token) => token,
+            Err(e) => {
+                log::error!("Error creating password reset for user {}: {}", user.id, e);
+                return;
+            }
+        };
+        if let Err(e) = password_reset::send_reset_email(&user, &token).await {
+            log::error!("Error sending password reset email to user {}: {}", user.id, e);
+        }
+    });
+
+    Controller::ok_empty("If the account exists, a password reset email has been sent.")
+}
+
+#[post("/password/reset")]
+pub async fn reset_password(
+    db: Data<DatabaseConnection>,
+    form: web::Json<ResetPasswordRequest>,
+) -> impl Responder {
+    if form.passwords.password != form.passwords.confirm_password {
+        return Controller::bad_request("Passwords do not match.");
+    }
+
+    // Check the new password before the token is spent, so a rejected password can be retried
+    let user_id = match password_reset::peek(db.get_ref(), &form.token).await {
+        Ok(user_id) => user_id,
+        Err(ResetError::Invalid) => return Controller::bad_request("Invalid or expired password reset token."),
+        Err(ResetError::Db(e)) => {
+            log::error!("Error looking up password reset token: {}", e);
+            return Controller::internal_server_error("Error resetting password.");
+        }
+    };
+
+    let user = match Controller::get_user_by_id(user_id, db.get_ref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let user_type = match UserType::from_str(&user.r#type) {
+        Ok(user_type) => user_type,
+        Err(_) => return Controller::internal_server_error("Error resetting password."),
+    };
+
+    if let Err(errors) = password_policy::validate(&form.passwords.password, &user_type).await {
+        return password_rejected(errors);
+    }
+
+    if let Err(err_response) = reject_reused_password(db.get_ref(), user.id, &form.passwords.password).await {
+        return err_response;
+    }
+
+    let user_id = match password_reset::consume(db.get_ref(), &form.token).await {
+        Ok(user_id) => user_id,
+        Err(ResetError::Invalid) => return Controller::bad_request("Invalid or expired password reset token."),
+        Err(ResetError::Db(e)) => {
+            log::error!("Error consuming password reset token: {}", e);
+            return Controller::internal_server_error("Error resetting password.");
+        }
+    };
+
+    let password_hash = match password::hash_password(&form.passwords.password).await {
+        Ok(password_hash) => password_hash,
+        Err(_) => return Controller::internal_server_error("Error hashing password."),
+    };
+
+    if password::replace_password(db.get_ref(), user_id, password_hash).await.is_err() {
+        return Controller::internal_server_error("Error saving password.");
+    }
+
+    // Sign the user out everywhere
+    if session::revoke_all_for_user(db.get_ref(), user_id, None).await.is_err() {
+        return Controller::internal_server_error("Error revoking existing sessions.");
+    }
+
+    Controller::ok_empty("Password reset successfully.")
+}
+
+#[post("/token/refresh")]
+pub async fn refresh_access_token(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<RefreshTokenRequest>,
+) -> impl Responder {
+    let consumed = match refresh_token::consume(db.get_ref(), &form.refresh_token).await {
+        Ok(consumed) => consumed,
+        Err(RefreshError::Invalid) => return Controller::unauthorized("Invalid refresh token."),
+        Err(RefreshError::Expired) => return Controller::unauthorized("Refresh token has expired."),
+        Err(RefreshError::Reused) => {
+            return Controller::unauthorized("Refresh token has already been used. Please log in again.")
+        }
+        Err(RefreshError::Db(e)) => {
+            log::error!("Error consuming refresh token: {}", e);
+            return Controller::internal_server_error("Error refreshing token.");
+        }
+    };
+
+    let user = match Controller::get_user_by_id(consumed.user_id, db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    if let Some(err_response) = Controller::reject_disabled(&user) {
+        return err_response;
+    }
+
+    if let Err(e) = session::resume(db.get_ref(), &consumed.family_id, user.id, &ClientInfo::from_request(&req)).await {
+        log::error!("Error updating session: {}", e);
+        return Controller::internal_server_error("Error refreshing token.");
+    }
+
+    match generate_token_pair(db.get_ref(), user, consumed.family_id).await {
+        Ok(response_data) => Controller::ok_with_data("Token refreshed successfully.", response_data),
+        Err(_) => Controller::internal_server_error("Error refreshing token."),
+    }
+}
+
 #[post("/logout")]
-pub async fn logout_user(req: HttpRequest) -> impl Responder {
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok());
-
-    if let Some(auth_header) = auth_header {
-        if auth_header.starts_with("Bearer ") {
-            let _token = &auth_header[7..];
-            return Controller::ok_empty("Logout successful.");
+pub async fn logout_user(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: Option<web::Json<LogoutRequest>>,
+) -> impl Responder {
+    let claims = match Controller::get_claims(&req) {
+        Some(claims) => claims,
+        None => return Controller::unauthorized("Unauthorized"),
+    };
+    let user_id = auth.user.id;
+
+    // Revoke only the presented session when a refresh token is given, otherwise every session.
+    // An impersonation token has no session of its own, so ending it leaves the user's sessions alone.
+    let result = match form.and_then(|form| form.into_inner().refresh_token) {
+        _ if auth.impersonator_id.is_some() => Ok(()),
+        Some(token) => match refresh_token::find(db.get_ref(), &token).await {
+            Ok(Some(record)) if record.user_id == user_id => {
+                session::revoke_by_session_id(db.get_ref(), &record.family_id).await
+            }
+            Ok(_) => return Controller::bad_request("Invalid refresh token."),
+            Err(e) => Err(e),
+        },
+        None => session::revoke_all_for_user(db.get_ref(), user_id, None).await.map(|_| ()),
+    };
+
+    // The access token used for this request stays on the denylist until it expires
+    let result = match result {
+        Ok(_) => revoked_token::revoke(db.get_ref(), &claims, user_id).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(_) => Controller::ok_empty("Logout successful."),
+        Err(_) => Controller::internal_server_error("Error logging out."),
+    }
+}
+
+/// Reject a request whose new password does not meet the policy
+fn password_rejected(errors: Vec<String>) -> HttpResponse {
+    let mut fields = FieldErrors::new();
+    fields.insert("password".to_string(), errors);
+    Controller::validation_error("The password does not meet the requirements.", fields)
+}
+
+/// Refuse a new password that matches one of the user's recent ones
+async fn reject_reused_password(db: &DatabaseConnection, user_id: i32, new_password: &str) -> Result<(), HttpResponse> {
+    let history = password::history(db, user_id)
+        .await
+        .map_err(|_| Controller::internal_server_error("Error checking password history."))?;
+    let hashes = history.into_iter().map(|stored| stored.password_hash).collect();
+
+    match password::matches_any(new_password, hashes).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(password_rejected(vec![format!(
+            "The password must differ from your last {} passwords.",
+            password::history_size()
+        )])),
+        Err(_) => Err(Controller::internal_server_error("Error checking password history.")),
+    }
+}
+
+/// Re-authenticate a signed in user before a sensitive change. Wrong passwords count
+/// against the login throttle, so a stolen access token cannot be used to guess the password.
+pub(crate) async fn check_current_password(
+    req: &HttpRequest,
+    db: &DatabaseConnection,
+    user: &Model,
+    current_password: &str,
+) -> Result<(), HttpResponse> {
+    let ip = login_throttle::client_ip(req);
+    match login_throttle::check(db, &user.email, &ip).await {
+        Ok(Some(retry_after)) => {
+            return Err(Controller::too_many_requests("Too many failed attempts. Please try again later.", retry_after))
+        }
+        Ok(None) => {}
+        Err(_) => return Err(Controller::internal_server_error("Error checking password.")),
+    }
+
+    let current = match password::current(db, user.id).await {
+        Ok(Some(current)) => current,
+        Ok(None) => {
+            return Err(Controller::bad_request("This account has no password yet. Use password reset to set one."))
+        }
+        Err(_) => return Err(Controller::internal_server_error("Error checking password.")),
+    };
+
+    match password::verify_password(current_password, Some(&current.password_hash)).await {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            if let Err(e) = login_throttle::record_failure(db, &user.email, &ip, Some(user.id)).await {
+                log::error!("Error recording failed password check: {}", e);
+            }
+            let mut fields = FieldErrors::new();
+            fields.insert("current_password".to_string(), vec!["The current password is incorrect.".to_string()]);
+            Err(Controller::validation_error("The current password is incorrect.", fields))
         }
+        Err(_) => Err(Controller::internal_server_error("Error checking password.")),
+    }
+}
+
+/// Change the signed in user's password and sign out their other sessions.
+/// Registered in `http::config` behind `DenyImpersonation`.
+pub async fn change_password(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: web::Json<ChangePasswordRequest>,
+) -> impl Responder {
+    let user = auth.user;
+
+    if form.passwords.password != form.passwords.confirm_password {
+        return Controller::bad_request("Passwords do not match.");
+    }
+
+    if let Err(err_response) = check_current_password(&req, db.get_ref(), &user, &form.current_password).await {
+        return err_response;
+    }
+
+    if let Err(errors) = password_policy::validate(&form.passwords.password, &auth.user_type).await {
+        return password_rejected(errors);
+    }
+
+    if let Err(err_response) = reject_reused_password(db.get_ref(), user.id, &form.passwords.password).await {
+        return err_response;
+    }
+
+    let password_hash = match password::hash_password(&form.passwords.password).await {
+        Ok(password_hash) => password_hash,
+        Err(_) => return Controller::internal_server_error("Error hashing password."),
+    };
+
+    if password::replace_password(db.get_ref(), user.id, password_hash).await.is_err() {
+        return Controller::internal_server_error("Error saving password.");
+    }
+
+    // Keep the session that made the change; every other device has to log in again
+    let current_session = Controller::get_claims(&req).and_then(|claims| claims.sid);
+    match session::revoke_all_for_user(db.get_ref(), user.id, current_session.as_deref()).await {
+        Ok(revoked) => Controller::ok_with_data(
+            "Password changed successfully.",
+            serde_json::json!({ "revoked_sessions": revoked }),
+        ),
+        Err(_) => Controller::internal_server_error("Error revoking other sessions."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use argon2::password_hash::{rand_core::OsRng, SaltString};
+    use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+    use chrono::{Duration, Utc};
+    use sea_orm::sea_query::Expr;
+    use sea_orm::{ConnectionTrait, EntityTrait, Schema, Statement};
+    use serde_json::json;
+    use crate::auth::{invitation, password, password_reset};
+    use crate::entities::{invitations, password_resets, passwords, user_wallet};
+    use crate::enums::user_type::UserType;
+    use crate::http::config::config;
+    use crate::test_support::{self, call_json, PASSWORD};
+
+    fn authorized(req: test::TestRequest, token: &str) -> actix_http::Request {
+        req.insert_header(("Authorization", format!("Bearer {}", token))).to_request()
+    }
+
+    #[actix_web::test]
+    async fn failed_registration_releases_the_invite() {
+        let db = test_support::database().await;
+        let admin = test_support::create_user(&db, UserType::Admin, "inviter@example.com").await;
+        let (invite, code) = invitation::create(&db, &UserType::Proctor, None, admin.id).await.unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        // Break the step after the user insert so the registration fails halfway through
+        db.execute(Statement::from_string(db.get_database_backend(), "DROP TABLE user_wallets".to_string()))
+            .await
+            .unwrap();
+
+        let registration = json!({
+            "email": "proctor@example.com",
+            "first_name": "Test",
+            "last_name": "Proctor",
+            "password": "Proctor password 2026",
+            "phone": "+10000000001",
+            "chain": "polkadot",
+            "invite_code": code,
+        });
+        let req = test::TestRequest::post().uri("/register/proctor").set_json(&registration).to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{}", body);
+
+        let invite = invitations::Entity::find_by_id(invite.id).one(&db).await.unwrap().unwrap();
+        assert!(invite.used_at.is_none());
+        assert!(invite.used_by.is_none());
+
+        // Once the table is back the same invite registers the proctor, who must enrol in 2FA first
+        let backend = db.get_database_backend();
+        db.execute(backend.build(&Schema::new(backend).create_table_from_entity(user_wallet::Entity)))
+            .await
+            .unwrap();
+        // The failed attempt left its user row behind
+        let cleanup = "DELETE FROM users WHERE email = 'proctor@example.com'".to_string();
+        db.execute(Statement::from_string(backend, cleanup)).await.unwrap();
+
+        let req = test::TestRequest::post().uri("/register/proctor").set_json(&registration).to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["mfa_required"], true);
+        assert_eq!(body["data"]["enrollment_required"], true);
+        assert!(body["data"]["token"].is_null());
+
+        let invite = invitations::Entity::find_by_id(invite.id).one(&db).await.unwrap().unwrap();
+        assert!(invite.used_at.is_some());
+    }
+
+    #[actix_web::test]
+    async fn reusing_a_rotated_refresh_token_revokes_the_family() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Member, "rotate@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+        let first = test_support::login(&app, "rotate@example.com").await["refresh_token"].clone();
+
+        let refresh = |token: &serde_json::Value| {
+            test::TestRequest::post().uri("/token/refresh").set_json(json!({ "refresh_token": token })).to_request()
+        };
+        let (status, body) = call_json(&app, refresh(&first)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let second = body["data"]["refresh_token"].clone();
+
+        // Replaying the rotated token also kills the token that replaced it
+        let (status, body) = call_json(&app, refresh(&first)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["message"], "Refresh token has already been used. Please log in again.");
+        let (status, _) = call_json(&app, refresh(&second)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
     }
 
-    Controller::bad_request("Authorization token missing or malformed.")
-}
\ No newline at end of file
+    #[actix_web::test]
+    async fn logged_out_access_token_is_rejected() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Member, "logout@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+        let token = test_support::login(&app, "logout@example.com").await["token"].as_str().unwrap().to_string();
+
+        let req = test::TestRequest::post()
+            .uri("/api/logout")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let req = test::TestRequest::get()
+            .uri("/api/user/details")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["message"], "Token has been revoked");
+    }
+
+    #[actix_web::test]
+    async fn reset_tokens_work_once_and_sign_out_every_session() {
+        let db = test_support::database().await;
+        let user = test_support::create_user(&db, UserType::Member, "reset@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+        let token = test_support::login(&app, "reset@example.com").await["token"].as_str().unwrap().to_string();
+        let reset_token = password_reset::create(&db, user.id).await.unwrap();
+
+        let reset = |reset_token: &str, password: &str| {
+            test::TestRequest::post()
+                .uri("/password/reset")
+                .set_json(json!({ "token": reset_token, "password": password, "confirm_password": password }))
+                .to_request()
+        };
+
+        // A recent password is refused without spending the token
+        let (status, _) = call_json(&app, reset(&reset_token, PASSWORD)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, body) = call_json(&app, reset(&reset_token, "reset password one")).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (status, _) = call_json(&app, authorized(test::TestRequest::get().uri("/api/user/details"), &token)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = call_json(&app, reset(&reset_token, "reset password two")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let expired_token = password_reset::create(&db, user.id).await.unwrap();
+        password_resets::Entity::update_many()
+            .col_expr(password_resets::Column::ExpiresAt, Expr::value(Utc::now() - Duration::minutes(1)))
+            .exec(&db)
+            .await
+            .unwrap();
+        let (status, _) = call_json(&app, reset(&expired_token, "reset password two")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn password_change_refuses_recent_passwords_and_signs_out_other_sessions() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Member, "change@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+        let current = test_support::login(&app, "change@example.com").await["token"].as_str().unwrap().to_string();
+        let other = test_support::login(&app, "change@example.com").await["token"].as_str().unwrap().to_string();
+
+        let change = |current_password: &str, password: &str| {
+            let body = json!({ "current_password": current_password, "password": password, "confirm_password": password });
+            authorized(test::TestRequest::post().uri("/api/user/password").set_json(body), &current)
+        };
+
+        let (status, _) = call_json(&app, change(PASSWORD, PASSWORD)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, body) = call_json(&app, change(PASSWORD, "changed password one")).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["revoked_sessions"], 1);
+        let (status, _) = call_json(&app, authorized(test::TestRequest::get().uri("/api/user/details"), &other)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call_json(&app, authorized(test::TestRequest::get().uri("/api/user/details"), &current)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // The password before the last one is still in the history
+        let (status, body) = call_json(&app, change("changed password one", PASSWORD)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["data"]["errors"]["password"].is_array());
+    }
+
+    #[actix_web::test]
+    async fn login_upgrades_hashes_made_with_older_parameters() {
+        let db = test_support::database().await;
+        let user = test_support::create_user(&db, UserType::Member, "rehash@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        let salt = SaltString::generate(&mut OsRng);
+        let old_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::new(2048, 2, 1, None).unwrap())
+            .hash_password(PASSWORD.as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+        assert!(password::needs_rehash(&old_hash));
+        passwords::Entity::update_many()
+            .col_expr(passwords::Column::PasswordHash, Expr::value(old_hash.clone()))
+            .exec(&db)
+            .await
+            .unwrap();
+
+        test_support::login(&app, "rehash@example.com").await;
+        let stored = password::current(&db, user.id).await.unwrap().unwrap().password_hash;
+        assert_ne!(stored, old_hash);
+        assert!(!password::needs_rehash(&stored));
+        assert!(password::verify_password(PASSWORD, Some(&stored)).await.unwrap());
+    }
+}
@@ -1,9 +1,9 @@
 use actix_web::{HttpMessage, HttpRequest, HttpResponse};
-use sea_orm::{DatabaseConnection, EntityTrait, QueryFilter, ColumnTrait};
+use sea_orm::{DatabaseConnection, QueryFilter, ColumnTrait};
 use serde::Serialize;
 use crate::entities::{users};
 use crate::http::middlewares::auth::Claims;
-use crate::http::response::ApiResponse;
+use crate::http::response::{ApiResponse, FieldErrors};
 /// Trait providing common controller utilities with standardized responses
 pub trait BaseController {
 
@@ -39,6 +39,15 @@ pub trait BaseController {
         })
     }
 
+    /// Generate a 403 Forbidden response
+    fn forbidden(message: &str) -> HttpResponse {
+        HttpResponse::Forbidden().json(ApiResponse::<()> {
+            success: false,
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
     /// Generate a 404 Not Found response
     fn not_found(message: &str) -> HttpResponse {
         HttpResponse::NotFound().json(ApiResponse::<()> {
@@ -57,6 +66,35 @@ pub trait BaseController {
         })
     }
 
+    /// Generate a 409 Conflict response
+    fn conflict(message: &str) -> HttpResponse {
+        HttpResponse::Conflict().json(ApiResponse::<()> {
+            success: false,
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    /// Generate a 422 Unprocessable Entity response listing the errors of each field
+    fn validation_error(message: &str, errors: FieldErrors) -> HttpResponse {
+        HttpResponse::UnprocessableEntity().json(ApiResponse {
+            success: false,
+            message: message.to_string(),
+            data: Some(serde_json::json!({ "errors": errors })),
+        })
+    }
+
+    /// Generate a 429 Too Many Requests response telling the client when to retry
+    fn too_many_requests(message: &str, retry_after_seconds: i64) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after_seconds.to_string()))
+            .json(ApiResponse::<()> {
+                success: false,
+                message: message.to_string(),
+                data: None,
+            })
+    }
+
     /// Generate a 500 Internal Server Error response
     fn internal_server_error(message: &str) -> HttpResponse {
         HttpResponse::InternalServerError().json(ApiResponse::<()> {
@@ -75,12 +113,26 @@ pub trait BaseController {
         })
     }
 
+    /// Generate a 202 Accepted response for work that finishes in the background
+    fn accepted<T: Serialize>(message: &str, data: T) -> HttpResponse {
+        HttpResponse::Accepted().json(ApiResponse {
+            success: true,
+            message: message.to_string(),
+            data: Some(data),
+        })
+    }
+
+    /// The response refusing to sign in an account an admin has disabled, if it is
+    fn reject_disabled(user: &users::Model) -> Option<HttpResponse> {
+        user.disabled_at.map(|_| Self::forbidden("This account has been disabled."))
+    }
+
     async fn get_user_by_id(
         user_id: i32,
         db: &DatabaseConnection
     ) -> Result<users::Model, HttpResponse>{
         // Retrieve the user to be updated
-        match users::Entity::find()
+        match users::Entity::find_active()
             .filter(users::Column::Id.eq(user_id))
             .one(db)
             .await
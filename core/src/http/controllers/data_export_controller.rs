@@ -0,0 +1,114 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Data;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use crate::auth::data_export::{self, ExportError};
+use crate::entities::{data_exports, users};
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+
+fn export_error_response(err: ExportError) -> HttpResponse {
+    match err {
+        // Requesting again while a job runs hands back that job rather than starting a second one
+        ExportError::InProgress(export) => Controller::accepted("An export is already being prepared.", export),
+        ExportError::Db(e) => {
+            log::error!("Error requesting data export: {}", e);
+            Controller::internal_server_error("Error requesting data export.")
+        }
+    }
+}
+
+/// Stream a finished archive as a JSON attachment
+async fn download(db: &DatabaseConnection, export_id: i32, user_id: Option<i32>) -> HttpResponse {
+    let mut query = data_exports::Entity::find_by_id(export_id);
+    if let Some(user_id) = user_id {
+        query = query.filter(data_exports::Column::UserId.eq(user_id));
+    }
+
+    let export = match query.one(db).await {
+        Ok(Some(export)) => export,
+        Ok(None) => return Controller::not_found("Export not found."),
+        Err(_) => return Controller::internal_server_error("Error retrieving export."),
+    };
+
+    match data_export::read_archive(&export).await {
+        Some(bytes) => HttpResponse::Ok()
+            .content_type("application/json")
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!("proctodot-export-{}.json", export.id))],
+            })
+            .body(bytes),
+        None => Controller::not_found("Export is not ready or has expired."),
+    }
+}
+
+#[post("")]
+pub async fn request_data_export(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match data_export::request(db.get_ref(), auth.user.id, auth.user.id).await {
+        Ok(export) => Controller::accepted("Export requested. It will be ready to download shortly.", export),
+        Err(err) => export_error_response(err),
+    }
+}
+
+#[get("")]
+pub async fn list_data_exports(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match data_export::list_for_user(db.get_ref(), auth.user.id).await {
+        Ok(exports) => Controller::ok_with_data("Exports retrieved successfully", exports),
+        Err(_) => Controller::internal_server_error("Error retrieving exports"),
+    }
+}
+
+#[get("/{id}/download")]
+pub async fn download_data_export(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    download(db.get_ref(), path.into_inner(), Some(auth.user.id)).await
+}
+
+/// Export on the user's behalf, including soft deleted accounts
+#[post("/users/{id}/exports")]
+pub async fn request_user_data_export(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user_id = path.into_inner();
+    match users::Entity::find_by_id(user_id).one(db.get_ref()).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Controller::not_found("User not found"),
+        Err(_) => return Controller::internal_server_error("Error requesting data export."),
+    }
+
+    match data_export::request(db.get_ref(), user_id, auth.user.id).await {
+        Ok(export) => Controller::accepted("Export requested. It will be ready to download shortly.", export),
+        Err(err) => export_error_response(err),
+    }
+}
+
+#[get("/users/{id}/exports")]
+pub async fn list_user_data_exports(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match data_export::list_for_user(db.get_ref(), path.into_inner()).await {
+        Ok(exports) => Controller::ok_with_data("Exports retrieved successfully", exports),
+        Err(_) => Controller::internal_server_error("Error retrieving exports"),
+    }
+}
+
+#[get("/exports/{id}/download")]
+pub async fn download_user_data_export(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    download(db.get_ref(), path.into_inner(), None).await
+}
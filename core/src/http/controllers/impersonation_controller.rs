@@ -0,0 +1,87 @@
+use actix_web::{get, post, web, HttpRequest, Responder};
+use actix_web::web::Data;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::Deserialize;
+use std::str::FromStr;
+use crate::auth::{impersonation, jwt};
+use crate::entities::impersonation_logs;
+use crate::enums::user_type::UserType;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+
+const DEFAULT_LOG_LIMIT: u64 = 100;
+const MAX_LOG_LIMIT: u64 = 1000;
+
+#[derive(Deserialize)]
+pub struct ImpersonationLogsQuery {
+    pub admin_id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub limit: Option<u64>,
+}
+
+/// Issue a token that lets the admin see the API as the given user. There is no
+/// refresh token; the admin has to start again once it expires.
+#[post("/impersonate/{user_id}")]
+pub async fn impersonate_user(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user = match Controller::get_user_by_id(path.into_inner(), db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    if user.id == auth.user.id {
+        return Controller::bad_request("You cannot impersonate yourself.");
+    }
+    if UserType::from_str(&user.r#type) == Ok(UserType::Admin) {
+        return Controller::forbidden("Admins cannot be impersonated.");
+    }
+
+    let (token, claims) = match jwt::encode_impersonation_token(user.id, auth.user.id) {
+        Ok(issued) => issued,
+        Err(_) => return Controller::internal_server_error("Error generating token."),
+    };
+
+    if let Err(e) = impersonation::record(db.get_ref(), auth.user.id, user.id, &claims.jti, "POST", req.path(), 201).await {
+        log::error!("Error recording impersonation of user {}: {}", user.id, e);
+        return Controller::internal_server_error("Error starting impersonation.");
+    }
+    log::warn!("Admin {} started impersonating user {}", auth.user.id, user.id);
+
+    Controller::created(
+        "Impersonation started.",
+        serde_json::json!({
+            "token": token,
+            "expires_in": jwt::impersonation_token_ttl().num_seconds(),
+            "impersonator_id": auth.user.id,
+            "user": user
+        }),
+    )
+}
+
+#[get("/impersonation-logs")]
+pub async fn list_impersonation_logs(
+    db: Data<DatabaseConnection>,
+    query: web::Query<ImpersonationLogsQuery>,
+) -> impl Responder {
+    let mut logs = impersonation_logs::Entity::find();
+    if let Some(admin_id) = query.admin_id {
+        logs = logs.filter(impersonation_logs::Column::AdminId.eq(admin_id));
+    }
+    if let Some(user_id) = query.user_id {
+        logs = logs.filter(impersonation_logs::Column::UserId.eq(user_id));
+    }
+
+    match logs
+        .order_by_desc(impersonation_logs::Column::CreatedAt)
+        .limit(query.limit.unwrap_or(DEFAULT_LOG_LIMIT).min(MAX_LOG_LIMIT))
+        .all(db.get_ref())
+        .await
+    {
+        Ok(logs) => Controller::ok_with_data("Impersonation logs retrieved successfully", logs),
+        Err(_) => Controller::internal_server_error("Error retrieving impersonation logs"),
+    }
+}
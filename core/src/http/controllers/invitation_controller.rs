@@ -0,0 +1,77 @@
+use actix_web::{delete, get, post, web, Responder};
+use actix_web::web::Data;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use serde::Deserialize;
+use std::str::FromStr;
+use crate::auth::invitation;
+use crate::entities::invitations;
+use crate::enums::user_type::UserType;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+
+#[derive(Deserialize)]
+pub struct CreateInvitationRequest {
+    pub role: String,
+    pub email: Option<String>,
+}
+
+#[post("/invitations")]
+pub async fn create_invitation(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: web::Json<CreateInvitationRequest>,
+) -> impl Responder {
+    let admin = auth.user;
+
+    let role = match UserType::from_str(&form.role) {
+        Ok(role) if invitation::required_for(&role) => role,
+        _ => return Controller::bad_request("Invitations can only be issued for 'proctor' or 'admin'."),
+    };
+
+    let (invitation, code) = match invitation::create(db.get_ref(), &role, form.email.clone(), admin.id).await {
+        Ok(created) => created,
+        Err(_) => return Controller::internal_server_error("Error creating invitation."),
+    };
+
+    // The code is only ever shown here; the invitee also gets it by email when one was given
+    if let Err(e) = invitation::send_invitation_email(&invitation, &code).await {
+        log::error!("Error sending invitation {}: {}", invitation.id, e);
+    }
+
+    Controller::created(
+        "Invitation created successfully.",
+        serde_json::json!({ "invite_code": code, "invitation": invitation }),
+    )
+}
+
+#[get("/invitations")]
+pub async fn list_invitations(
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match invitations::Entity::find()
+        .order_by_desc(invitations::Column::CreatedAt)
+        .all(db.get_ref())
+        .await
+    {
+        Ok(invitations) => Controller::ok_with_data("Invitations retrieved successfully", invitations),
+        Err(_) => Controller::internal_server_error("Error retrieving invitations"),
+    }
+}
+
+#[delete("/invitations/{id}")]
+pub async fn revoke_invitation(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    // Redeemed invites are kept as a record of who created which account
+    match invitations::Entity::delete_many()
+        .filter(invitations::Column::Id.eq(path.into_inner()))
+        .filter(invitations::Column::UsedAt.is_null())
+        .exec(db.get_ref())
+        .await
+    {
+        Ok(result) if result.rows_affected > 0 => Controller::ok_empty("Invitation revoked successfully."),
+        Ok(_) => Controller::not_found("Invitation not found"),
+        Err(_) => Controller::internal_server_error("Error revoking invitation"),
+    }
+}
@@ -0,0 +1,11 @@
+use actix_web::{get, HttpResponse, Responder};
+use crate::auth::signing_keys::KEYS;
+
+/// Public signing keys for services that verify proctodot access tokens.
+/// Served as a bare JWK Set rather than an `ApiResponse`, as JWKS clients expect.
+#[get("/.well-known/jwks.json")]
+pub async fn jwks() -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(KEYS.jwks())
+}
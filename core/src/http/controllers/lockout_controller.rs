@@ -0,0 +1,72 @@
+use actix_web::{post, web, Responder};
+use actix_web::web::Data;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use serde::Deserialize;
+use crate::auth::login_throttle;
+use crate::entities::lockout_events;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+
+const DEFAULT_EVENT_LIMIT: u64 = 50;
+const MAX_EVENT_LIMIT: u64 = 500;
+
+#[derive(Deserialize)]
+pub struct LockoutEventsQuery {
+    pub user_id: Option<i32>,
+    pub email: Option<String>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct UnlockRequest {
+    pub email: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+pub async fn list_lockout_events(
+    db: Data<DatabaseConnection>,
+    query: web::Query<LockoutEventsQuery>,
+) -> impl Responder {
+    let mut events = lockout_events::Entity::find();
+    if let Some(user_id) = query.user_id {
+        events = events.filter(lockout_events::Column::UserId.eq(user_id));
+    }
+    if let Some(email) = &query.email {
+        events = events.filter(lockout_events::Column::Email.eq(email.trim().to_lowercase()));
+    }
+
+    match events
+        .order_by_desc(lockout_events::Column::CreatedAt)
+        .limit(query.limit.unwrap_or(DEFAULT_EVENT_LIMIT).min(MAX_EVENT_LIMIT))
+        .all(db.get_ref())
+        .await
+    {
+        Ok(events) => Controller::ok_with_data("Lockout events retrieved successfully", events),
+        Err(_) => Controller::internal_server_error("Error retrieving lockout events"),
+    }
+}
+
+#[post("/login-locks/unlock")]
+pub async fn unlock_login(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: web::Json<UnlockRequest>,
+) -> impl Responder {
+    let requester = auth.user;
+
+    if form.email.is_none() && form.ip_address.is_none() {
+        return Controller::bad_request("Provide an email or an IP address to unlock.");
+    }
+
+    match login_throttle::unlock(
+        db.get_ref(),
+        form.email.as_deref(),
+        form.ip_address.as_deref(),
+        requester.id,
+    )
+    .await
+    {
+        Ok(_) => Controller::ok_empty("Login unlocked successfully."),
+        Err(_) => Controller::internal_server_error("Error unlocking login."),
+    }
+}
@@ -0,0 +1,116 @@
+use actix_web::{post, web, HttpRequest, Responder};
+use actix_web::web::Data;
+use sea_orm::{ColumnTrait, DatabaseConnection, QueryFilter};
+use serde::Deserialize;
+use crate::auth::magic_link::{self, Action, MagicLinkError};
+use crate::entities::users;
+use crate::http::controllers::auth_controller::finish_login;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkRedeemRequest {
+    pub token: String,
+}
+
+#[post("/login/magic-link")]
+pub async fn send_magic_link(
+    db: Data<DatabaseConnection>,
+    form: web::Json<MagicLinkRequest>,
+) -> impl Responder {
+    if let Some(retry_after) = magic_link::throttle(Action::Send, &form.email) {
+        return Controller::too_many_requests("Too many login links requested. Please try again later.", retry_after);
+    }
+
+    let user = users::Entity::find_active()
+        .filter(users::Column::Email.eq(form.email.clone()))
+        .one(db.get_ref())
+        .await;
+
+    match user {
+        Ok(Some(user)) => {
+            if let Err(e) = magic_link::send_login_email(&user).await {
+                log::error!("Error sending login link to user {}: {}", user.id, e);
+            }
+        }
+        Ok(None) => {}
+        Err(_) => return Controller::internal_server_error("Error sending login link."),
+    }
+
+    // Respond the same way whether or not the address exists
+    Controller::ok_empty("If the account exists, a login link has been sent.")
+}
+
+#[post("/login/magic-link/redeem")]
+pub async fn redeem_magic_link(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<MagicLinkRedeemRequest>,
+) -> impl Responder {
+    let (user_id, email) = match magic_link::redeem(db.get_ref(), &form.token).await {
+        Ok(redeemed) => redeemed,
+        Err(MagicLinkError::Invalid) => return Controller::unauthorized("Invalid or expired login link."),
+        Err(MagicLinkError::RateLimited(retry_after)) => {
+            return Controller::too_many_requests("Too many login attempts. Please try again later.", retry_after)
+        }
+        Err(MagicLinkError::Db(e)) => {
+            log::error!("Error redeeming login link: {}", e);
+            return Controller::internal_server_error("Error logging in.");
+        }
+    };
+
+    let user = match Controller::get_user_by_id(user_id, db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    // Links die with an email change, since they were sent to the old address
+    if user.email != email {
+        return Controller::unauthorized("Invalid or expired login link.");
+    }
+
+    // The link only stands in for the password; accounts with 2FA still need their code
+    finish_login(db.get_ref(), user, &req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use serde_json::json;
+    use crate::auth::magic_link;
+    use crate::enums::user_type::UserType;
+    use crate::http::config::config;
+    use crate::test_support::{self, call_json};
+
+    async fn redeem(user_type: UserType, email: &str) -> (StatusCode, serde_json::Value) {
+        let db = test_support::database().await;
+        let user = test_support::create_user(&db, user_type, email).await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/login/magic-link/redeem")
+            .set_json(json!({ "token": magic_link::create_token(&user).unwrap() }))
+            .to_request();
+        call_json(&app, req).await
+    }
+
+    #[actix_web::test]
+    async fn link_signs_in_an_account_without_two_factor() {
+        let (status, body) = redeem(UserType::Member, "link-member@example.com").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["data"]["token"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn link_still_needs_the_second_factor() {
+        let (status, body) = redeem(UserType::Admin, "link-admin@example.com").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["mfa_required"], true);
+        assert!(body["data"]["token"].is_null());
+    }
+}
@@ -0,0 +1,398 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::web::Data;
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+use std::future::Future;
+use crate::auth::login_throttle;
+use crate::auth::mfa::{self, MfaError, MfaToken};
+use crate::entities::users;
+use crate::http::controllers::auth_controller::generate_user_response_with_token;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+
+#[derive(Deserialize)]
+pub struct MfaLoginRequest {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaSetupRequest {
+    pub mfa_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct MfaCodeRequest {
+    pub code: String,
+}
+
+fn mfa_error_response(err: MfaError) -> HttpResponse {
+    match err {
+        MfaError::NotEnrolled => Controller::bad_request("Two-factor authentication is not set up."),
+        MfaError::AlreadyEnabled => Controller::bad_request("Two-factor authentication is already enabled."),
+        MfaError::InvalidCode => Controller::unauthorized("Invalid authentication code."),
+        MfaError::Encryption(e) => {
+            log::error!("Error handling TOTP secret: {}", e);
+            Controller::internal_server_error("Error processing two-factor authentication.")
+        }
+        MfaError::Db(e) => {
+            log::error!("Error processing two-factor authentication: {}", e);
+            Controller::internal_server_error("Error processing two-factor authentication.")
+        }
+    }
+}
+
+/// Resolve the user behind an intermediate MFA token that has not been exchanged yet
+async fn user_from_mfa_token(
+    token: &str,
+    purpose: &str,
+    db: &DatabaseConnection,
+) -> Result<(users::Model, MfaToken), HttpResponse> {
+    let token = mfa::verify_mfa_token(token, purpose).map_err(Controller::unauthorized)?;
+    match mfa::is_consumed(db, &token).await {
+        Ok(false) => {}
+        Ok(true) => return Err(Controller::unauthorized("Invalid or expired MFA token")),
+        Err(_) => return Err(Controller::internal_server_error("Error logging in.")),
+    }
+    let user = Controller::get_user_by_id(token.user_id, db).await?;
+    Ok((user, token))
+}
+
+/// Check a code under the password login throttle, since codes are guessable too.
+/// A wrong code counts as a failed login; a right one burns the intermediate token of a
+/// login, if any, and clears the failures the password step left in place.
+async fn check_code<T>(
+    req: &HttpRequest,
+    db: &DatabaseConnection,
+    user: &users::Model,
+    token: Option<&MfaToken>,
+    check: impl Future<Output = Result<T, MfaError>>,
+) -> Result<T, HttpResponse> {
+    let ip = login_throttle::client_ip(req);
+    match login_throttle::check(db, &user.email, &ip).await {
+        Ok(Some(retry_after)) => {
+            return Err(Controller::too_many_requests(
+                "Too many failed login attempts. Please try again later.",
+                retry_after,
+            ))
+        }
+        Ok(None) => {}
+        Err(_) => return Err(Controller::internal_server_error("Error logging in.")),
+    }
+
+    let result = match check.await {
+        Ok(result) => result,
+        Err(MfaError::InvalidCode) => {
+            if let Err(e) = login_throttle::record_failure(db, &user.email, &ip, Some(user.id)).await {
+                log::error!("Error recording failed login attempt: {}", e);
+            }
+            return Err(mfa_error_response(MfaError::InvalidCode));
+        }
+        Err(err) => return Err(mfa_error_response(err)),
+    };
+
+    if let Some(token) = token {
+        if mfa::consume(db, token).await.is_err() {
+            return Err(Controller::unauthorized("Invalid or expired MFA token"));
+        }
+    }
+    if login_throttle::record_success(db, &user.email).await.is_err() {
+        return Err(Controller::internal_server_error("Error logging in."));
+    }
+
+    Ok(result)
+}
+
+/// Second step of a password login: exchange the intermediate token and a code for a JWT
+#[post("/login/mfa")]
+pub async fn login_mfa(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<MfaLoginRequest>,
+) -> impl Responder {
+    let (user, token) = match user_from_mfa_token(&form.mfa_token, mfa::PURPOSE_CHALLENGE, db.get_ref()).await {
+        Ok(result) => result,
+        Err(err_response) => return err_response,
+    };
+
+    let verification = mfa::verify_code(db.get_ref(), user.id, &form.code);
+    if let Err(err_response) = check_code(&req, db.get_ref(), &user, Some(&token), verification).await {
+        return err_response;
+    }
+
+    if let Some(err_response) = Controller::reject_disabled(&user) {
+        return err_response;
+    }
+
+    match generate_user_response_with_token(db.get_ref(), user, &req).await {
+        Ok(response_data) => Controller::ok_with_data("Login successful", response_data),
+        Err(_) => Controller::internal_server_error("Error generating token."),
+    }
+}
+
+/// Enrolment for users whose role requires 2FA but who have not set it up yet
+#[post("/login/mfa/setup")]
+pub async fn login_mfa_setup(
+    db: Data<DatabaseConnection>,
+    form: web::Json<MfaSetupRequest>,
+) -> impl Responder {
+    let (user, _) = match user_from_mfa_token(&form.mfa_token, mfa::PURPOSE_ENROLL, db.get_ref()).await {
+        Ok(result) => result,
+        Err(err_response) => return err_response,
+    };
+
+    match mfa::begin_enrollment(db.get_ref(), &user).await {
+        Ok(enrollment) => Controller::ok_with_data("Scan the provisioning URI with your authenticator app.", enrollment),
+        Err(err) => mfa_error_response(err),
+    }
+}
+
+/// Finish a required enrolment and sign the user in
+#[post("/login/mfa/activate")]
+pub async fn login_mfa_activate(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<MfaLoginRequest>,
+) -> impl Responder {
+    let (user, token) = match user_from_mfa_token(&form.mfa_token, mfa::PURPOSE_ENROLL, db.get_ref()).await {
+        Ok(result) => result,
+        Err(err_response) => return err_response,
+    };
+
+    let activation = mfa::activate(db.get_ref(), user.id, &form.code);
+    let recovery_codes = match check_code(&req, db.get_ref(), &user, Some(&token), activation).await {
+        Ok(recovery_codes) => recovery_codes,
+        Err(err_response) => return err_response,
+    };
+
+    if let Some(err_response) = Controller::reject_disabled(&user) {
+        return err_response;
+    }
+
+    match generate_user_response_with_token(db.get_ref(), user, &req).await {
+        Ok(mut response_data) => {
+            response_data["recovery_codes"] = serde_json::json!(recovery_codes);
+            Controller::ok_with_data("Two-factor authentication enabled.", response_data)
+        }
+        Err(_) => Controller::internal_server_error("Error generating token."),
+    }
+}
+
+#[post("/totp/setup")]
+pub async fn setup_totp(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user = auth.user;
+
+    match mfa::begin_enrollment(db.get_ref(), &user).await {
+        Ok(enrollment) => Controller::ok_with_data("Scan the provisioning URI with your authenticator app.", enrollment),
+        Err(err) => mfa_error_response(err),
+    }
+}
+
+#[post("/totp/activate")]
+pub async fn activate_totp(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: web::Json<MfaCodeRequest>,
+) -> impl Responder {
+    let user = auth.user;
+
+    let activation = mfa::activate(db.get_ref(), user.id, &form.code);
+    match check_code(&req, db.get_ref(), &user, None, activation).await {
+        Ok(recovery_codes) => Controller::ok_with_data(
+            "Two-factor authentication enabled.",
+            serde_json::json!({ "recovery_codes": recovery_codes }),
+        ),
+        Err(err_response) => err_response,
+    }
+}
+
+#[post("/totp/disable")]
+pub async fn disable_totp(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: web::Json<MfaCodeRequest>,
+) -> impl Responder {
+    let user = auth.user;
+
+    if mfa::required_for(&auth.user_type) {
+        return Controller::bad_request("Two-factor authentication is required for your account type.");
+    }
+
+    let verification = mfa::verify_code(db.get_ref(), user.id, &form.code);
+    if let Err(err_response) = check_code(&req, db.get_ref(), &user, None, verification).await {
+        return err_response;
+    }
+
+    match mfa::disable(db.get_ref(), user.id).await {
+        Ok(_) => Controller::ok_empty("Two-factor authentication disabled."),
+        Err(_) => Controller::internal_server_error("Error disabling two-factor authentication."),
+    }
+}
+
+#[post("/recovery-codes")]
+pub async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: web::Json<MfaCodeRequest>,
+) -> impl Responder {
+    let user = auth.user;
+
+    let verification = mfa::verify_code(db.get_ref(), user.id, &form.code);
+    if let Err(err_response) = check_code(&req, db.get_ref(), &user, None, verification).await {
+        return err_response;
+    }
+
+    match mfa::regenerate_recovery_codes(db.get_ref(), user.id).await {
+        Ok(recovery_codes) => Controller::ok_with_data(
+            "Recovery codes regenerated.",
+            serde_json::json!({ "recovery_codes": recovery_codes }),
+        ),
+        Err(_) => Controller::internal_server_error("Error regenerating recovery codes."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+    use serde_json::json;
+    use crate::auth::totp;
+    use crate::entities::login_attempts;
+    use crate::enums::user_type::UserType;
+    use crate::http::config::config;
+    use crate::test_support::{self, call_json};
+
+    #[actix_web::test]
+    async fn required_enrolment_is_throttled_and_its_token_single_use() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Admin, "enrol@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        let challenge = test_support::login(&app, "enrol@example.com").await;
+        assert_eq!(challenge["enrollment_required"], true);
+        let mfa_token = challenge["mfa_token"].clone();
+
+        let req = test::TestRequest::post()
+            .uri("/login/mfa/setup")
+            .set_json(json!({ "mfa_token": mfa_token }))
+            .to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let secret = body["data"]["secret"].as_str().unwrap().to_string();
+
+        // A wrong code counts against the account like a wrong password
+        let req = test::TestRequest::post()
+            .uri("/login/mfa/activate")
+            .set_json(json!({ "mfa_token": mfa_token, "code": "abcdef" }))
+            .to_request();
+        let (status, _) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let failures = login_attempts::Entity::find()
+            .filter(login_attempts::Column::KeyValue.eq("enrol@example.com"))
+            .one(&db)
+            .await
+            .unwrap()
+            .expect("failure recorded");
+        assert_eq!(failures.failed_count, 1);
+
+        let req = test::TestRequest::post()
+            .uri("/login/mfa/activate")
+            .set_json(json!({ "mfa_token": mfa_token, "code": totp::current_code(&secret) }))
+            .to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["data"]["token"].is_string());
+        assert!(login_attempts::Entity::find()
+            .filter(login_attempts::Column::KeyValue.eq("enrol@example.com"))
+            .one(&db)
+            .await
+            .unwrap()
+            .is_none());
+
+        // The intermediate token cannot be exchanged a second time
+        let req = test::TestRequest::post()
+            .uri("/login/mfa/setup")
+            .set_json(json!({ "mfa_token": mfa_token }))
+            .to_request();
+        let (status, _) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn password_step_alone_does_not_clear_failures() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Admin, "failures@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .set_json(json!({ "email": "failures@example.com", "password": "wrong password" }))
+            .to_request();
+        let (status, _) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let challenge = test_support::login(&app, "failures@example.com").await;
+        assert_eq!(challenge["mfa_required"], true);
+        assert!(login_attempts::Entity::find()
+            .filter(login_attempts::Column::KeyValue.eq("failures@example.com"))
+            .one(&db)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[actix_web::test]
+    async fn mfa_token_is_not_an_access_token() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Admin, "bearer@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        let challenge = test_support::login(&app, "bearer@example.com").await;
+        let mfa_token = challenge["mfa_token"].as_str().unwrap();
+
+        let req = test::TestRequest::get()
+            .uri("/api/user/details")
+            .insert_header(("Authorization", format!("Bearer {}", mfa_token)))
+            .to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+    }
+
+    #[actix_web::test]
+    async fn codes_for_signed_in_users_are_throttled() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Member, "guess@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+        let token = test_support::login(&app, "guess@example.com").await["token"].as_str().unwrap().to_string();
+        let post = |uri: &str, body: serde_json::Value| {
+            test::TestRequest::post()
+                .uri(uri)
+                .insert_header(("Authorization", format!("Bearer {}", token)))
+                .set_json(body)
+                .to_request()
+        };
+
+        let (status, body) = call_json(&app, post("/api/mfa/totp/setup", json!({}))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let secret = body["data"]["secret"].as_str().unwrap().to_string();
+        let activation = json!({ "code": totp::current_code(&secret) });
+        let (status, body) = call_json(&app, post("/api/mfa/totp/activate", activation)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        for uri in ["/api/mfa/totp/disable", "/api/mfa/recovery-codes", "/api/mfa/totp/disable"] {
+            let (status, _) = call_json(&app, post(uri, json!({ "code": "000000" }))).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        for uri in ["/api/mfa/totp/disable", "/api/mfa/recovery-codes"] {
+            let (status, _) = call_json(&app, post(uri, json!({ "code": "000000" }))).await;
+            assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        }
+    }
+}
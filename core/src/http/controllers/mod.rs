@@ -1,4 +1,17 @@
 pub mod base_controller;
 
 pub mod auth_controller;
-pub mod user_controller;
\ No newline at end of file
+pub mod user_controller;
+pub mod mfa_controller;
+pub mod passkey_controller;
+pub mod oidc_controller;
+pub mod wallet_auth_controller;
+pub mod lockout_controller;
+pub mod invitation_controller;
+pub mod jwks_controller;
+pub mod api_key_controller;
+pub mod impersonation_controller;
+pub mod magic_link_controller;
+pub mod session_controller;
+pub mod admin_user_controller;
+pub mod data_export_controller;
\ No newline at end of file
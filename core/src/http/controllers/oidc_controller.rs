@@ -0,0 +1,224 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::web::Data;
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+use crate::auth::oidc::{self, OidcError, OidcProvider};
+use crate::http::controllers::auth_controller::{finish_login, generate_wallet};
+use crate::http::controllers::base_controller::{BaseController, Controller};
+
+#[derive(Deserialize)]
+pub struct OidcCallbackRequest {
+    pub code: String,
+    pub state: String,
+}
+
+fn oidc_error_response(err: OidcError) -> HttpResponse {
+    match err {
+        OidcError::UnknownProvider => Controller::not_found("Identity provider not found"),
+        OidcError::InvalidState => Controller::bad_request("Invalid or expired login state."),
+        OidcError::MissingEmail => Controller::bad_request("The identity provider did not share an email address."),
+        OidcError::UnverifiedEmail => {
+            Controller::bad_request("The identity provider has not verified this email address.")
+        }
+        OidcError::Provider(e) => {
+            log::error!("OIDC provider error: {}", e);
+            Controller::unauthorized("Could not sign in with the identity provider.")
+        }
+        OidcError::AccountDeleted => Controller::forbidden("This account has been deleted."),
+        OidcError::PrivilegedAccount => {
+            Controller::forbidden("This account cannot be linked to an identity provider. Sign in with your password.")
+        }
+        OidcError::Db(e) => {
+            log::error!("Error processing OIDC login: {}", e);
+            Controller::internal_server_error("Error logging in.")
+        }
+    }
+}
+
+#[get("/oidc/{provider}/authorize")]
+pub async fn oidc_authorize(provider: web::Path<String>) -> impl Responder {
+    let provider = match OidcProvider::from_env(&provider.into_inner()) {
+        Some(provider) => provider,
+        None => return oidc_error_response(OidcError::UnknownProvider),
+    };
+
+    match oidc::authorization_url(&provider).await {
+        Ok(authorization_url) => Controller::ok_with_data(
+            "Redirect the user to the authorization URL.",
+            serde_json::json!({ "authorization_url": authorization_url }),
+        ),
+        Err(err) => oidc_error_response(err),
+    }
+}
+
+#[post("/oidc/{provider}/callback")]
+pub async fn oidc_callback(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    provider: web::Path<String>,
+    form: web::Json<OidcCallbackRequest>,
+) -> impl Responder {
+    let provider = match OidcProvider::from_env(&provider.into_inner()) {
+        Some(provider) => provider,
+        None => return oidc_error_response(OidcError::UnknownProvider),
+    };
+
+    let info = match oidc::exchange_code(&provider, &form.code, &form.state).await {
+        Ok(info) => info,
+        Err(err) => return oidc_error_response(err),
+    };
+
+    let (user, created) = match oidc::find_or_create_user(db.get_ref(), &provider, &info).await {
+        Ok(result) => result,
+        Err(err) => return oidc_error_response(err),
+    };
+
+    // First login through the provider: give the new user a wallet like a regular registration
+    if created && generate_wallet(db.clone(), user.id).await.is_err() {
+        return Controller::internal_server_error("Error saving wallet information.");
+    }
+
+    finish_login(db.get_ref(), user, &req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App, HttpRequest, HttpResponse, HttpServer};
+    use oauth2::url::Url;
+    use sea_orm::sea_query::Expr;
+    use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+    use serde_json::{json, Value};
+    use std::env;
+    use std::sync::Mutex;
+    use crate::auth::mfa;
+    use crate::entities::{user_identities, user_mfa, users};
+    use crate::enums::user_type::UserType;
+    use crate::http::config::config;
+    use crate::test_support::{self, call_json};
+
+    static PROVIDERS: Mutex<()> = Mutex::new(());
+
+    async fn discovery(req: HttpRequest) -> HttpResponse {
+        let issuer = format!("http://{}", req.connection_info().host());
+        HttpResponse::Ok().json(json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "userinfo_endpoint": format!("{}/userinfo", issuer),
+        }))
+    }
+
+    async fn token() -> HttpResponse {
+        HttpResponse::Ok().json(json!({ "access_token": "mock-access-token", "token_type": "bearer" }))
+    }
+
+    async fn userinfo(claims: web::Data<Value>) -> HttpResponse {
+        HttpResponse::Ok().json(claims.get_ref())
+    }
+
+    /// Serve a minimal identity provider answering userinfo with `claims`, and enable it as `name`
+    async fn mock_provider(name: &str, claims: Value) {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(claims.clone()))
+                .route("/.well-known/openid-configuration", web::get().to(discovery))
+                .route("/token", web::post().to(token))
+                .route("/userinfo", web::get().to(userinfo))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let issuer = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        // Tests run in parallel, so each one registers its own provider name
+        let _guard = PROVIDERS.lock().unwrap();
+        let prefix = format!("OIDC_{}", name.to_uppercase().replace('-', "_"));
+        env::set_var(format!("{}_ISSUER_URL", prefix), issuer);
+        env::set_var(format!("{}_CLIENT_ID", prefix), "proctodot");
+        env::set_var(format!("{}_CLIENT_SECRET", prefix), "secret");
+        let providers = env::var("OIDC_PROVIDERS").unwrap_or_default();
+        env::set_var("OIDC_PROVIDERS", format!("{},{}", providers, name));
+    }
+
+    /// Run authorize and callback against the mock provider and return the callback response
+    async fn sign_in(db: &DatabaseConnection, provider: &str) -> (StatusCode, Value) {
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        let req = test::TestRequest::get().uri(&format!("/oidc/{}/authorize", provider)).to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let authorization_url = Url::parse(body["data"]["authorization_url"].as_str().unwrap()).unwrap();
+        let state = authorization_url
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, value)| value.into_owned())
+            .unwrap();
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/oidc/{}/callback", provider))
+            .set_json(json!({ "code": "mock-code", "state": state }))
+            .to_request();
+        call_json(&app, req).await
+    }
+
+    fn claims(sub: &str, email: &str) -> Value {
+        json!({ "sub": sub, "email": email, "email_verified": true, "given_name": "Ada", "family_name": "Lovelace" })
+    }
+
+    #[actix_web::test]
+    async fn first_login_creates_and_links_a_member() {
+        let db = test_support::database().await;
+        mock_provider("mock-new", claims("subject-1", "new@example.com")).await;
+
+        let (status, body) = sign_in(&db, "mock-new").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["data"]["token"].is_string());
+
+        let user = users::Entity::find()
+            .filter(users::Column::Email.eq("new@example.com"))
+            .one(&db)
+            .await
+            .unwrap()
+            .expect("user created");
+        assert_eq!(user.r#type, UserType::Member.to_string());
+        let identity = user_identities::Entity::find().one(&db).await.unwrap().expect("identity linked");
+        assert_eq!(identity.user_id, user.id);
+
+        // The next login goes through the linked identity instead of creating another account
+        let (status, _) = sign_in(&db, "mock-new").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(users::Entity::find().all(&db).await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn member_with_two_factor_gets_the_challenge() {
+        let db = test_support::database().await;
+        let user = test_support::create_user(&db, UserType::Member, "mfa@example.com").await;
+        mfa::begin_enrollment(&db, &user).await.unwrap();
+        user_mfa::Entity::update_many()
+            .col_expr(user_mfa::Column::EnabledAt, Expr::value(chrono::Utc::now()))
+            .filter(user_mfa::Column::UserId.eq(user.id))
+            .exec(&db)
+            .await
+            .unwrap();
+        mock_provider("mock-mfa", claims("subject-2", "mfa@example.com")).await;
+
+        let (status, body) = sign_in(&db, "mock-mfa").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["mfa_required"], true);
+        assert!(body["data"]["token"].is_null());
+    }
+
+    #[actix_web::test]
+    async fn admin_account_is_not_linked_by_email() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Admin, "admin@example.com").await;
+        mock_provider("mock-admin", claims("subject-3", "admin@example.com")).await;
+
+        let (status, body) = sign_in(&db, "mock-admin").await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+        assert!(user_identities::Entity::find().one(&db).await.unwrap().is_none());
+    }
+}
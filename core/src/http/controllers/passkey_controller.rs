@@ -0,0 +1,316 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::web::Data;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::Deserialize;
+use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential};
+use crate::auth::login_throttle;
+use crate::auth::passkey::{self, PasskeyError};
+use crate::entities::{passkey_credentials, users};
+use crate::http::controllers::auth_controller::finish_login;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+
+#[derive(Deserialize)]
+pub struct FinishPasskeyRegistrationRequest {
+    pub ceremony_id: String,
+    pub name: Option<String>,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Deserialize)]
+pub struct StartPasskeyLoginRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct FinishPasskeyLoginRequest {
+    pub ceremony_id: String,
+    pub credential: PublicKeyCredential,
+}
+
+fn passkey_error_response(err: PasskeyError) -> HttpResponse {
+    match err {
+        PasskeyError::UnknownCeremony => Controller::bad_request("Unknown or expired passkey ceremony."),
+        PasskeyError::NoCredentials => Controller::bad_request("No passkeys are registered for this account."),
+        PasskeyError::Busy => Controller::too_many_requests("Too many passkey ceremonies in progress. Please try again later.", 60),
+        PasskeyError::Webauthn(e) => {
+            log::warn!("Passkey verification failed: {:?}", e);
+            Controller::unauthorized("Passkey verification failed.")
+        }
+        PasskeyError::Serialization(e) => {
+            log::error!("Error (de)serializing passkey: {}", e);
+            Controller::internal_server_error("Error processing passkey.")
+        }
+        PasskeyError::Db(e) => {
+            log::error!("Error processing passkey: {}", e);
+            Controller::internal_server_error("Error processing passkey.")
+        }
+    }
+}
+
+#[post("/register/start")]
+pub async fn start_passkey_registration(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user = auth.user;
+
+    match passkey::start_registration(db.get_ref(), &user).await {
+        Ok((ceremony_id, options)) => Controller::ok_with_data(
+            "Passkey registration started.",
+            serde_json::json!({ "ceremony_id": ceremony_id, "options": options }),
+        ),
+        Err(err) => passkey_error_response(err),
+    }
+}
+
+#[post("/register/finish")]
+pub async fn finish_passkey_registration(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+    form: web::Json<FinishPasskeyRegistrationRequest>,
+) -> impl Responder {
+    let user = auth.user;
+
+    let form = form.into_inner();
+    match passkey::finish_registration(db.get_ref(), user.id, &form.ceremony_id, form.name, &form.credential).await {
+        Ok(credential) => Controller::created("Passkey registered successfully.", credential),
+        Err(err) => passkey_error_response(err),
+    }
+}
+
+#[get("")]
+pub async fn list_passkeys(
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user = auth.user;
+
+    match passkey_credentials::Entity::find()
+        .filter(passkey_credentials::Column::UserId.eq(user.id))
+        .all(db.get_ref())
+        .await
+    {
+        Ok(credentials) => Controller::ok_with_data("Passkeys retrieved successfully", credentials),
+        Err(_) => Controller::internal_server_error("Error retrieving passkeys"),
+    }
+}
+
+#[delete("/{id}")]
+pub async fn delete_passkey(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user = auth.user;
+
+    match passkey_credentials::Entity::delete_many()
+        .filter(passkey_credentials::Column::Id.eq(path.into_inner()))
+        .filter(passkey_credentials::Column::UserId.eq(user.id))
+        .exec(db.get_ref())
+        .await
+    {
+        Ok(result) if result.rows_affected > 0 => Controller::ok_empty("Passkey deleted successfully."),
+        Ok(_) => Controller::not_found("Passkey not found"),
+        Err(_) => Controller::internal_server_error("Error deleting passkey"),
+    }
+}
+
+#[post("/login/passkey/start")]
+pub async fn start_passkey_login(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<StartPasskeyLoginRequest>,
+) -> impl Responder {
+    if let Some(retry_after) = passkey::throttle_login(&login_throttle::client_ip(&req)) {
+        return Controller::too_many_requests("Too many passkey login attempts. Please try again later.", retry_after);
+    }
+
+    let user = match users::Entity::find_active()
+        .filter(users::Column::Email.eq(form.email.clone()))
+        .one(db.get_ref())
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return passkey_error_response(PasskeyError::NoCredentials),
+        Err(_) => return Controller::internal_server_error("Error logging in."),
+    };
+
+    match passkey::start_authentication(db.get_ref(), &user).await {
+        Ok((ceremony_id, options)) => Controller::ok_with_data(
+            "Passkey login started.",
+            serde_json::json!({ "ceremony_id": ceremony_id, "options": options }),
+        ),
+        Err(err) => passkey_error_response(err),
+    }
+}
+
+/// A passkey replaces the password only; accounts that use 2FA still get the MFA challenge
+#[post("/login/passkey/finish")]
+pub async fn finish_passkey_login(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<FinishPasskeyLoginRequest>,
+) -> impl Responder {
+    let user_id = match passkey::finish_authentication(db.get_ref(), &form.ceremony_id, &form.credential).await {
+        Ok(user_id) => user_id,
+        Err(err) => return passkey_error_response(err),
+    };
+
+    let user = match Controller::get_user_by_id(user_id, db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    finish_login(db.get_ref(), user, &req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_http::Request;
+    use actix_web::body::MessageBody;
+    use actix_web::dev::{Service, ServiceResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+    use webauthn_authenticator_rs::WebauthnAuthenticator;
+    use webauthn_rs::prelude::{CreationChallengeResponse, RequestChallengeResponse, Url};
+    use crate::auth::{mfa, totp};
+    use crate::enums::user_type::UserType;
+    use crate::http::config::config;
+    use crate::test_support::{self, call_json, FRONTEND_URL};
+
+    type Authenticator = WebauthnAuthenticator<SoftPasskey>;
+
+    /// Register a passkey held by the software authenticator for the signed-in user
+    async fn register<S, B>(app: &S, token: &str, authenticator: &mut Authenticator)
+    where
+        S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+        B: MessageBody,
+    {
+        // The server's creation options go through the authenticator and back
+        let req = test::TestRequest::post()
+            .uri("/api/passkeys/register/start")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request();
+        let (status, body) = call_json(app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let options: CreationChallengeResponse = serde_json::from_value(body["data"]["options"].clone()).unwrap();
+        let credential = authenticator.do_registration(Url::parse(FRONTEND_URL).unwrap(), options).unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/api/passkeys/register/finish")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(serde_json::json!({
+                "ceremony_id": body["data"]["ceremony_id"],
+                "name": "Test key",
+                "credential": credential,
+            }))
+            .to_request();
+        let (status, body) = call_json(app, req).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(body["data"]["name"], "Test key");
+    }
+
+    /// Start a passkey login and return the finish request body signed by the authenticator
+    async fn sign_login<S, B>(app: &S, email: &str, authenticator: &mut Authenticator) -> serde_json::Value
+    where
+        S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+        B: MessageBody,
+    {
+        let req = test::TestRequest::post()
+            .uri("/login/passkey/start")
+            .set_json(serde_json::json!({ "email": email }))
+            .to_request();
+        let (status, body) = call_json(app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let options: RequestChallengeResponse = serde_json::from_value(body["data"]["options"].clone()).unwrap();
+        let credential = authenticator.do_authentication(Url::parse(FRONTEND_URL).unwrap(), options).unwrap();
+
+        serde_json::json!({ "ceremony_id": body["data"]["ceremony_id"], "credential": credential })
+    }
+
+    #[actix_web::test]
+    async fn registers_and_signs_in_with_a_software_authenticator() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Member, "passkey@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+        let token = test_support::login(&app, "passkey@example.com").await["token"].as_str().unwrap().to_string();
+
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+        register(&app, &token, &mut authenticator).await;
+
+        // The assertion signed by the same authenticator yields a token pair
+        let finish = sign_login(&app, "passkey@example.com", &mut authenticator).await;
+        let req = test::TestRequest::post().uri("/login/passkey/finish").set_json(&finish).to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(body["data"]["token"].is_string());
+        assert_eq!(body["data"]["user"]["email"], "passkey@example.com");
+
+        // A ceremony can only be finished once
+        let req = test::TestRequest::post().uri("/login/passkey/finish").set_json(&finish).to_request();
+        let (status, _) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn passkey_login_still_requires_the_second_factor() {
+        let db = test_support::database().await;
+        let proctor = test_support::create_user(&db, UserType::Proctor, "passkey-proctor@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        // Proctors must enrol in TOTP before their first session
+        let challenge = test_support::login(&app, "passkey-proctor@example.com").await;
+        assert_eq!(challenge["enrollment_required"], true);
+        let req = test::TestRequest::post()
+            .uri("/login/mfa/setup")
+            .set_json(serde_json::json!({ "mfa_token": challenge["mfa_token"] }))
+            .to_request();
+        let (_, body) = call_json(&app, req).await;
+        let secret = body["data"]["secret"].as_str().unwrap().to_string();
+        let req = test::TestRequest::post()
+            .uri("/login/mfa/activate")
+            .set_json(serde_json::json!({ "mfa_token": challenge["mfa_token"], "code": totp::current_code(&secret) }))
+            .to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let token = body["data"]["token"].as_str().unwrap().to_string();
+
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+        register(&app, &token, &mut authenticator).await;
+
+        let finish = sign_login(&app, "passkey-proctor@example.com", &mut authenticator).await;
+        let req = test::TestRequest::post().uri("/login/passkey/finish").set_json(&finish).to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["mfa_required"], true);
+        assert_eq!(body["data"]["enrollment_required"], false);
+        assert!(body["data"]["token"].is_null());
+
+        // Without an enrolment the passkey only leads to the enrolment step
+        mfa::disable(&db, proctor.id).await.unwrap();
+        let finish = sign_login(&app, "passkey-proctor@example.com", &mut authenticator).await;
+        let req = test::TestRequest::post().uri("/login/passkey/finish").set_json(&finish).to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["enrollment_required"], true);
+        assert!(body["data"]["token"].is_null());
+    }
+
+    #[actix_web::test]
+    async fn refuses_to_start_a_login_without_registered_passkeys() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Member, "no-passkey@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        for email in ["no-passkey@example.com", "unknown@example.com"] {
+            let req = test::TestRequest::post()
+                .uri("/login/passkey/start")
+                .set_json(serde_json::json!({ "email": email }))
+                .to_request();
+            let (status, _) = call_json(&app, req).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use actix_web::{delete, get, web, HttpRequest, Responder};
+use actix_web::web::Data;
+use sea_orm::DatabaseConnection;
+use crate::auth::session;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+
+#[get("")]
+pub async fn list_sessions(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let current = Controller::get_claims(&req).and_then(|claims| claims.sid);
+
+    match session::list_for_user(db.get_ref(), auth.user.id).await {
+        Ok(sessions) => {
+            let sessions: Vec<serde_json::Value> = sessions
+                .into_iter()
+                .map(|record| {
+                    let is_current = current.as_deref() == Some(record.session_id.as_str());
+                    let mut value = serde_json::json!(record);
+                    value["current"] = serde_json::json!(is_current);
+                    value
+                })
+                .collect();
+            Controller::ok_with_data("Sessions retrieved successfully", sessions)
+        }
+        Err(_) => Controller::internal_server_error("Error retrieving sessions"),
+    }
+}
+
+/// Sign out every other device, keeping the session making the request
+#[delete("")]
+pub async fn revoke_other_sessions(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let current = Controller::get_claims(&req).and_then(|claims| claims.sid);
+
+    match session::revoke_all_for_user(db.get_ref(), auth.user.id, current.as_deref()).await {
+        Ok(revoked) => Controller::ok_with_data("Other sessions revoked.", serde_json::json!({ "revoked": revoked })),
+        Err(_) => Controller::internal_server_error("Error revoking sessions."),
+    }
+}
+
+#[delete("/{id}")]
+pub async fn revoke_session(
+    auth: AuthenticatedUser,
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    match session::revoke(db.get_ref(), auth.user.id, path.into_inner()).await {
+        Ok(true) => Controller::ok_empty("Session revoked."),
+        Ok(false) => Controller::not_found("Session not found."),
+        Err(_) => Controller::internal_server_error("Error revoking session."),
+    }
+}
+
+/// Sign a user out of every device, e.g. after their account was compromised
+#[delete("/users/{user_id}/sessions")]
+pub async fn revoke_user_sessions(
+    path: web::Path<i32>,
+    db: Data<DatabaseConnection>,
+) -> impl Responder {
+    let user = match Controller::get_user_by_id(path.into_inner(), db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    match session::revoke_all_for_user(db.get_ref(), user.id, None).await {
+        Ok(revoked) => Controller::ok_with_data("Sessions revoked.", serde_json::json!({ "revoked": revoked })),
+        Err(_) => Controller::internal_server_error("Error revoking sessions."),
+    }
+}
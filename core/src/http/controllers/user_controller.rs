@@ -1,9 +1,12 @@
-use actix_web::{get, HttpRequest, Responder, web};
+use actix_web::{get, post, HttpRequest, HttpResponse, Responder, web};
+use chrono::Utc;
+use crate::auth::contact_change::{self, ContactChangeError};
+use crate::http::controllers::auth_controller::check_current_password;
 use crate::http::controllers::base_controller::{BaseController, Controller};
 use crate::entities::{users, user_wallet};
-use crate::enums::user_type::UserType;
-use sea_orm::{EntityTrait, ColumnTrait, QueryFilter};
-use std::str::FromStr;
+use crate::http::middlewares::auth::AuthenticatedUser;
+use crate::http::validation::ProfileUpdate;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, QueryFilter, Set};
 use serde::{Serialize, Deserialize};
 
 #[derive(Serialize, Deserialize)]
@@ -12,55 +15,61 @@ pub struct UserWithWallet {
     pub wallet_address: Option<String>,
 }
 
-#[get("/user/details")]
-pub async fn user_details(
-    req: HttpRequest,
-    db: web::Data<sea_orm::DatabaseConnection>,
-) -> impl Responder {
+#[derive(Deserialize)]
+pub struct UpdateProfileRequest {
+    #[serde(flatten)]
+    pub profile: ProfileUpdate,
+    /// Required when the email changes, since the address is what account recovery relies on
+    pub current_password: Option<String>,
+}
 
-    let claims = Controller::get_claims(&req);
-    if claims.is_none() {
-        return Controller::unauthorized("Unauthorized");
-    }
+#[derive(Deserialize)]
+pub struct ConfirmEmailChangeRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmPhoneChangeRequest {
+    pub code: String,
+}
 
-    let user_id: i32 = claims.unwrap().sub.parse().unwrap();
-    match Controller::get_user_by_id(user_id, db.get_ref()).await {
-        Ok(user) => Controller::ok_with_data(
-            "User details retrieved successfully",
-            Some(user),
-        ),
-        Err(err_response) => err_response,
+fn contact_change_error_response(err: ContactChangeError) -> HttpResponse {
+    match err {
+        ContactChangeError::Invalid => Controller::bad_request("Invalid or expired confirmation."),
+        ContactChangeError::WrongCode => Controller::bad_request("The verification code is incorrect."),
+        ContactChangeError::TooManyAttempts => {
+            Controller::bad_request("Too many incorrect codes. Please request the phone change again.")
+        }
+        ContactChangeError::Db(e) => {
+            log::error!("Error confirming contact change: {}", e);
+            Controller::internal_server_error("Error updating profile.")
+        }
     }
 }
 
-#[get("/user/{id}/details")]
+/// Whether another live account already uses the address
+async fn email_taken(db: &DatabaseConnection, email: &str) -> Result<bool, HttpResponse> {
+    users::Entity::find_active()
+        .filter(users::Column::Email.eq(email))
+        .one(db)
+        .await
+        .map(|user| user.is_some())
+        .map_err(|_| Controller::internal_server_error("Error updating profile."))
+}
+
+#[get("/user/details")]
+pub async fn user_details(auth: AuthenticatedUser) -> impl Responder {
+    Controller::ok_with_data(
+        "User details retrieved successfully",
+        Some(auth.user),
+    )
+}
+
+/// Restricted to proctors and admins by the `RequireRole` guard registered in `http::config`
 pub async fn get_user_details_by_id(
-    req: HttpRequest,
     path: web::Path<i32>,
     db: web::Data<sea_orm::DatabaseConnection>,
 ) -> impl Responder {
-    let claims = Controller::get_claims(&req);
-    if claims.is_none() {
-        return Controller::unauthorized("Unauthorized");
-    }
-
-    // Get the requesting user's ID and check if they're a proctor
-    let requester_id: i32 = claims.unwrap().sub.parse().unwrap();
-    let requester = match Controller::get_user_by_id(requester_id, db.get_ref()).await {
-        Ok(user) => user,
-        Err(err_response) => return err_response,
-    };
-
-    // Check if the requester is a proctor
-    let user_type = match UserType::from_str(&requester.r#type) {
-        Ok(user_type) => user_type,
-        Err(_) => return Controller::bad_request("Invalid user type"),
-    };
-
-    if user_type != UserType::Proctor && user_type != UserType::Admin {
-        return Controller::unauthorized("Only proctors and admins can access user details");
-    }
-
     // Get the requested user's details
     let requested_user_id = path.into_inner();
     let user = match Controller::get_user_by_id(requested_user_id, db.get_ref()).await {
@@ -69,7 +78,7 @@ pub async fn get_user_details_by_id(
     };
 
     // Get the user's wallet address
-    let wallet_address = match user_wallet::Entity::find()
+    let wallet_address = match user_wallet::Entity::find_active()
         .filter(user_wallet::Column::UserId.eq(user.id as i64))
         .one(db.get_ref())
         .await {
@@ -88,3 +97,218 @@ pub async fn get_user_details_by_id(
         Some(user_with_wallet),
     )
 }
+
+/// Update the signed in user's profile. Names change straight away; a new email
+/// or phone only replaces the current one once it has been confirmed, and a new email
+/// also needs the current password.
+/// Registered in `http::config` behind `DenyImpersonation`.
+pub async fn update_user_details(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    db: web::Data<DatabaseConnection>,
+    form: web::Json<UpdateProfileRequest>,
+) -> impl Responder {
+    let user = auth.user;
+    let profile = &form.profile;
+    let mut errors = profile.validate();
+
+    let new_email = profile.email.as_deref().map(str::trim).filter(|email| *email != user.email);
+    if let Some(email) = new_email.filter(|_| !errors.contains_key("email")) {
+        match email_taken(db.get_ref(), email).await {
+            Ok(true) => {
+                errors.insert("email".to_string(), vec!["Email is already taken.".to_string()]);
+            }
+            Ok(false) => {}
+            Err(err_response) => return err_response,
+        }
+    }
+    let current_password = form.current_password.as_deref().filter(|password| !password.is_empty());
+    if new_email.is_some() && current_password.is_none() {
+        errors.insert(
+            "current_password".to_string(),
+            vec!["The current password is required to change the email address.".to_string()],
+        );
+    }
+
+    if !errors.is_empty() {
+        return Controller::validation_error("The given data was invalid.", errors);
+    }
+
+    if let (Some(_), Some(current_password)) = (new_email, current_password) {
+        if let Err(err_response) = check_current_password(&req, db.get_ref(), &user, current_password).await {
+            return err_response;
+        }
+    }
+
+    let new_phone = profile.phone.as_deref().map(str::trim).filter(|phone| *phone != user.phone);
+    if let Some(phone) = new_phone {
+        if let Some(retry_after) = contact_change::throttle_phone_change(user.id) {
+            return Controller::too_many_requests("Too many phone changes requested. Please try again later.", retry_after);
+        }
+        if let Err(e) = contact_change::request_phone_change(db.get_ref(), user.id, phone).await {
+            return contact_change_error_response(e);
+        }
+    }
+    if let Some(email) = new_email {
+        if let Some(retry_after) = contact_change::throttle_email_change(user.id) {
+            return Controller::too_many_requests("Too many email changes requested. Please try again later.", retry_after);
+        }
+        if let Err(e) = contact_change::request_email_change(&user, email).await {
+            log::error!("Error sending email change confirmation to user {}: {}", user.id, e);
+            return Controller::internal_server_error("Error sending confirmation email.");
+        }
+    }
+
+    let first_name = profile.first_name.as_deref().map(str::trim).filter(|name| *name != user.first_name);
+    let last_name = profile.last_name.as_deref().map(str::trim).filter(|name| *name != user.last_name);
+
+    let user = if first_name.is_some() || last_name.is_some() {
+        let mut active_user: users::ActiveModel = user.into();
+        if let Some(first_name) = first_name {
+            active_user.first_name = Set(first_name.to_string());
+        }
+        if let Some(last_name) = last_name {
+            active_user.last_name = Set(last_name.to_string());
+        }
+        active_user.updated_at = Set(Utc::now());
+
+        match active_user.update(db.get_ref()).await {
+            Ok(user) => user,
+            Err(_) => return Controller::internal_server_error("Error updating profile."),
+        }
+    } else {
+        user
+    };
+
+    Controller::ok_with_data(
+        "Profile updated successfully.",
+        serde_json::json!({
+            "user": user,
+            // Confirmation was sent to these; they apply once confirmed
+            "pending": { "email": new_email, "phone": new_phone },
+        }),
+    )
+}
+
+/// Opened from the link sent to the new address, so it works without a session
+#[post("/user/email/confirm")]
+pub async fn confirm_email_change(
+    db: web::Data<DatabaseConnection>,
+    form: web::Json<ConfirmEmailChangeRequest>,
+) -> impl Responder {
+    let (user_id, current_email, new_email) = match contact_change::verify_email_change(&form.token) {
+        Ok(change) => change,
+        Err(err) => return contact_change_error_response(err),
+    };
+
+    let user = match Controller::get_user_by_id(user_id, db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    // A link goes stale once the address it was requested from is no longer the account's
+    if user.email != current_email {
+        return contact_change_error_response(ContactChangeError::Invalid);
+    }
+
+    match email_taken(db.get_ref(), &new_email).await {
+        Ok(true) => return Controller::conflict("Email is already taken."),
+        Ok(false) => {}
+        Err(err_response) => return err_response,
+    }
+
+    let now = Utc::now();
+    let mut active_user: users::ActiveModel = user.into();
+    active_user.email = Set(new_email);
+    // Opening the link proved the new address belongs to the user
+    active_user.verified_at = Set(Some(now));
+    active_user.updated_at = Set(now);
+
+    let user = match active_user.update(db.get_ref()).await {
+        Ok(user) => user,
+        Err(_) => return Controller::internal_server_error("Error updating profile."),
+    };
+
+    if let Err(e) = contact_change::notify_email_changed(&user, &current_email).await {
+        log::error!("Error notifying user {} of their email change: {}", user.id, e);
+    }
+
+    Controller::ok_with_data("Email address updated successfully.", user)
+}
+
+/// Registered in `http::config` behind `DenyImpersonation`
+pub async fn confirm_phone_change(
+    auth: AuthenticatedUser,
+    db: web::Data<DatabaseConnection>,
+    form: web::Json<ConfirmPhoneChangeRequest>,
+) -> impl Responder {
+    let phone = match contact_change::confirm_phone_change(db.get_ref(), auth.user.id, &form.code).await {
+        Ok(phone) => phone,
+        Err(err) => return contact_change_error_response(err),
+    };
+
+    let mut active_user: users::ActiveModel = auth.user.into();
+    active_user.phone = Set(phone);
+    active_user.updated_at = Set(Utc::now());
+
+    match active_user.update(db.get_ref()).await {
+        Ok(user) => Controller::ok_with_data("Phone number updated successfully.", user),
+        Err(_) => Controller::internal_server_error("Error updating profile."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use serde_json::{json, Value};
+    use crate::enums::user_type::UserType;
+    use crate::http::config::config;
+    use crate::test_support::{self, call_json, PASSWORD};
+
+    fn update(token: &str, body: Value) -> actix_http::Request {
+        test::TestRequest::patch()
+            .uri("/api/user/details")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(body)
+            .to_request()
+    }
+
+    #[actix_web::test]
+    async fn email_change_needs_the_current_password() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Member, "before@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+        let token = test_support::login(&app, "before@example.com").await["token"].as_str().unwrap().to_string();
+
+        let (status, body) = call_json(&app, update(&token, json!({ "email": "after@example.com" }))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        assert!(body["data"]["errors"]["current_password"].is_array());
+
+        let request = json!({ "email": "after@example.com", "current_password": "not the password" });
+        let (status, body) = call_json(&app, update(&token, request)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+
+        let request = json!({ "email": "after@example.com", "current_password": PASSWORD });
+        let (status, body) = call_json(&app, update(&token, request)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["pending"]["email"], "after@example.com");
+        // Only the confirmation link moves the account to the new address
+        assert_eq!(body["data"]["user"]["email"], "before@example.com");
+    }
+
+    #[actix_web::test]
+    async fn phone_changes_are_rate_limited() {
+        let db = test_support::database().await;
+        test_support::create_user(&db, UserType::Member, "phone@example.com").await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+        let token = test_support::login(&app, "phone@example.com").await["token"].as_str().unwrap().to_string();
+
+        for n in 1..=3 {
+            let (status, body) = call_json(&app, update(&token, json!({ "phone": format!("+4470000000{}", n) }))).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+        }
+        let (status, _) = call_json(&app, update(&token, json!({ "phone": "+447000000004" }))).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
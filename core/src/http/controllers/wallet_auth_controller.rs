@@ -0,0 +1,154 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use actix_web::web::Data;
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+use crate::auth::login_throttle;
+use crate::auth::wallet_challenge::{self, ChallengeError};
+use crate::http::controllers::auth_controller::finish_login;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::web3::wallet_handler::WalletHandler;
+
+#[derive(Deserialize)]
+pub struct WalletChallengeRequest {
+    pub address: String,
+}
+
+#[derive(Deserialize)]
+pub struct WalletVerifyRequest {
+    pub address: String,
+    pub nonce: String,
+    /// Hex encoded sr25519 signature, with or without a 0x prefix
+    pub signature: String,
+}
+
+fn challenge_error_response(err: ChallengeError) -> HttpResponse {
+    match err {
+        ChallengeError::InvalidChallenge => Controller::unauthorized("Invalid or expired challenge."),
+        ChallengeError::InvalidSignature | ChallengeError::UnknownWallet => {
+            Controller::unauthorized("Invalid wallet signature.")
+        }
+        ChallengeError::Db(e) => {
+            log::error!("Error verifying wallet challenge: {}", e);
+            Controller::internal_server_error("Error logging in.")
+        }
+    }
+}
+
+#[post("/login/wallet/challenge")]
+pub async fn create_wallet_challenge(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<WalletChallengeRequest>,
+) -> impl Responder {
+    if let Some(retry_after) = wallet_challenge::throttle(&login_throttle::client_ip(&req)) {
+        return Controller::too_many_requests("Too many wallet login attempts. Please try again later.", retry_after);
+    }
+
+    if !WalletHandler::is_valid_address(&form.address) {
+        return Controller::bad_request("Invalid wallet address.");
+    }
+
+    // Challenges are issued for any address so they do not reveal which wallets exist
+    match wallet_challenge::create(db.get_ref(), &form.address).await {
+        Ok(challenge) => Controller::ok_with_data(
+            "Sign the message with your wallet.",
+            serde_json::json!({
+                "nonce": challenge.nonce,
+                "message": challenge.message,
+                "expires_at": challenge.expires_at,
+            }),
+        ),
+        Err(_) => Controller::internal_server_error("Error creating challenge."),
+    }
+}
+
+#[post("/login/wallet/verify")]
+pub async fn verify_wallet_challenge(
+    req: HttpRequest,
+    db: Data<DatabaseConnection>,
+    form: web::Json<WalletVerifyRequest>,
+) -> impl Responder {
+    let user_id = match wallet_challenge::verify(db.get_ref(), &form.address, &form.nonce, &form.signature).await {
+        Ok(user_id) => user_id,
+        Err(err) => return challenge_error_response(err),
+    };
+
+    let user = match Controller::get_user_by_id(user_id, db.get_ref()).await {
+        Ok(user) => user,
+        Err(err_response) => return err_response,
+    };
+
+    finish_login(db.get_ref(), user, &req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use chrono::Utc;
+    use sea_orm::{EntityTrait, Set};
+    use sp_core::crypto::Ss58Codec;
+    use sp_core::{sr25519, Pair};
+    use crate::entities::user_wallet;
+    use crate::enums::user_type::UserType;
+    use crate::http::config::config;
+    use crate::test_support::{self, call_json};
+
+    #[actix_web::test]
+    async fn rejects_malformed_addresses() {
+        let db = test_support::database().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        for address in ["not-an-address", &"5".repeat(4096)] {
+            let req = test::TestRequest::post()
+                .uri("/login/wallet/challenge")
+                .set_json(serde_json::json!({ "address": address }))
+                .to_request();
+            let (status, body) = call_json(&app, req).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        }
+    }
+
+    #[actix_web::test]
+    async fn signed_challenge_still_needs_the_second_factor() {
+        let db = test_support::database().await;
+        let user = test_support::create_user(&db, UserType::Admin, "wallet-admin@example.com").await;
+        let (pair, _) = sr25519::Pair::generate();
+        let address = pair.public().to_ss58check();
+        user_wallet::Entity::insert(user_wallet::ActiveModel {
+            user_id: Set(user.id as i64),
+            encrypted_mnemonic: Set(String::new()),
+            encrypted_private_key: Set(String::new()),
+            public_key: Set(address.clone()),
+            address: Set(address.clone()),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await
+        .unwrap();
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        let req = test::TestRequest::post()
+            .uri("/login/wallet/challenge")
+            .set_json(serde_json::json!({ "address": address }))
+            .to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let signature = pair.sign(body["data"]["message"].as_str().unwrap().as_bytes());
+
+        let req = test::TestRequest::post()
+            .uri("/login/wallet/verify")
+            .set_json(serde_json::json!({
+                "address": address,
+                "nonce": body["data"]["nonce"],
+                "signature": format!("0x{}", hex::encode(signature.0)),
+            }))
+            .to_request();
+        let (status, body) = call_json(&app, req).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["mfa_required"], true);
+        assert!(body["data"]["token"].is_null());
+    }
+}
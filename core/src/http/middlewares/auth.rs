@@ -1,24 +1,185 @@
 use actix_web::{
     body::{BoxBody, MessageBody},
-    dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    Error, HttpMessage, HttpResponse, web,
+    dev::{Extensions, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder, web,
 };
-use futures::future::{ok, Ready};
+use futures::future::{err, ok, Ready};
 use futures::Future;
-use jsonwebtoken::{decode, Validation, DecodingKey};
 use serde::{Deserialize, Serialize};
 use std::task::{Context, Poll};
 use std::pin::Pin;
 use std::rc::Rc;
-use sea_orm::EntityTrait; // Import sea_orm for database queries
+use std::str::FromStr;
 use crate::entities::users; // Import the users entity
 use sea_orm::DatabaseConnection; // Import DatabaseConnection
 use crate::http::response::ApiResponse;
+use crate::auth::{api_key, email_verification, impersonation, jwt, login_throttle, revoked_token, session};
+use crate::enums::user_type::UserType;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    pub jti: String,
+    /// Always `jwt::ACCESS_TOKEN_TYPE`, so no other token signed with the same key passes as one
+    pub typ: String,
+    /// Set on impersonation tokens: the admin acting as `sub` (RFC 8693 actor claim)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    /// The login session the token was issued for; impersonation tokens have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Actor {
+    pub sub: String,
+}
+
+/// The user behind the access token, loaded once by `AuthMiddleware` and
+/// available to handlers as an extractor
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user: users::Model,
+    pub user_type: UserType,
+    /// The admin behind an impersonation token
+    pub impersonator_id: Option<i32>,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if !api_key_permitted(&req.extensions()) {
+            let res = HttpResponse::Forbidden()
+                .json(ApiResponse::<()> {
+                    success: false,
+                    message: "API keys cannot access this endpoint".to_string(),
+                    data: None,
+                });
+            return err(InternalError::from_response("API key not permitted", res).into());
+        }
+
+        match req.extensions().get::<AuthenticatedUser>() {
+            Some(user) => ok(user.clone()),
+            None => {
+                // Only reachable when a handler outside the AuthMiddleware scope asks for a user
+                let res = HttpResponse::Unauthorized()
+                    .json(ApiResponse::<()> {
+                        success: false,
+                        message: "Unauthorized".to_string(),
+                        data: None,
+                    });
+                err(InternalError::from_response("Unauthorized", res).into())
+            }
+        }
+    }
+}
+
+/// Present when the request was authenticated with an API key instead of a user token
+#[derive(Debug, Clone)]
+pub struct ApiKeyScopes {
+    pub key_id: i32,
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+impl ApiKeyScopes {
+    pub fn has(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+}
+
+impl FromRequest for ApiKeyScopes {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    /// Use `Option<ApiKeyScopes>` in handlers that serve both users and API keys
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        match req.extensions().get::<ApiKeyScopes>() {
+            Some(scopes) => ok(scopes.clone()),
+            None => {
+                let res = HttpResponse::Unauthorized()
+                    .json(ApiResponse::<()> {
+                        success: false,
+                        message: "An API key is required".to_string(),
+                        data: None,
+                    });
+                err(InternalError::from_response("API key required", res).into())
+            }
+        }
+    }
+}
+
+/// Set by `RequireScope` once an API key's scopes have been checked for the route
+pub(crate) struct ScopeGranted;
+
+/// API keys only reach routes that declare the scope they need with `RequireScope`
+pub(crate) fn api_key_permitted(extensions: &Extensions) -> bool {
+    !extensions.contains::<ApiKeyScopes>() || extensions.contains::<ScopeGranted>()
+}
+
+/// Answer the request with an error in the standard response shape instead of calling the service
+fn reject(req: ServiceRequest, mut response: HttpResponseBuilder, message: &str) -> ServiceResponse<BoxBody> {
+    let res = response.json(ApiResponse::<()> {
+        success: false,
+        message: message.to_string(),
+        data: None,
+    });
+    req.into_response(res.map_into_boxed_body())
+}
+
+/// Authenticate an integration by API key; requests act on behalf of the admin who created the key
+async fn authenticate_api_key<S, B>(
+    service: Rc<S>,
+    req: ServiceRequest,
+    db: Option<web::Data<DatabaseConnection>>,
+    key: &str,
+) -> Result<ServiceResponse<BoxBody>, Error>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    let db = match db {
+        Some(db) => db,
+        None => return Ok(reject(req, HttpResponse::InternalServerError(), "Database connection not available")),
+    };
+
+    let record = match api_key::authenticate(db.get_ref(), key).await {
+        Ok(Some(record)) => record,
+        Ok(None) => return Ok(reject(req, HttpResponse::Unauthorized(), "Invalid API key")),
+        Err(_) => return Ok(reject(req, HttpResponse::InternalServerError(), "Error checking API key")),
+    };
+
+    let owner = match users::Entity::find_active_by_id(record.created_by).one(db.get_ref()).await {
+        Ok(Some(owner)) if owner.disabled_at.is_some() => {
+            return Ok(reject(req, HttpResponse::Forbidden(), "Account has been disabled"))
+        }
+        Ok(Some(owner)) => owner,
+        Ok(None) => return Ok(reject(req, HttpResponse::Unauthorized(), "Invalid API key")),
+        Err(_) => return Ok(reject(req, HttpResponse::InternalServerError(), "Error checking user in the database")),
+    };
+    let user_type = match UserType::from_str(&owner.r#type) {
+        Ok(user_type) => user_type,
+        Err(_) => return Ok(reject(req, HttpResponse::Forbidden(), "Invalid user type")),
+    };
+
+    req.extensions_mut().insert(ApiKeyScopes {
+        key_id: record.id,
+        scopes: record.scope_list(),
+        name: record.name,
+    });
+    req.extensions_mut().insert(AuthenticatedUser { user: owner, user_type, impersonator_id: None });
+    let res = service.call(req).await?;
+    Ok(res.map_into_boxed_body())
+}
+
+/// Tokens in the query string end up in proxy and access logs, so they are only
+/// accepted when ALLOW_QUERY_STRING_TOKENS is set
+fn query_string_tokens_allowed() -> bool {
+    std::env::var("ALLOW_QUERY_STRING_TOKENS").map(|value| value == "true").unwrap_or(false)
 }
 
 pub struct AuthMiddleware;
@@ -69,7 +230,7 @@ where
             // Check for token in Authorization header or query parameter
             let token = if let Some(auth_header) = req.headers().get("Authorization") {
                 Some(auth_header.to_str().unwrap_or("").replace("Bearer ", ""))
-            } else {
+            } else if query_string_tokens_allowed() {
                 // Check for token in query parameters
                 let query = req.query_string();
                 if let Some(token_value) = query.split('&')
@@ -79,87 +240,217 @@ where
                 } else {
                     None
                 }
+            } else {
+                None
             };
 
-            if let Some(token) = token {
-                let secret = std::env::var("SECRET_KEY").expect("SECRET_KEY must be set");
-                let key = DecodingKey::from_secret(secret.as_ref());
+            // Integrations send an API key in a header instead of a user token
+            let api_key = req
+                .headers()
+                .get("X-Api-Key")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+                .or_else(|| token.clone().filter(|_| req.headers().contains_key("Authorization")))
+                .filter(|key| api_key::is_api_key(key));
+            if let Some(key) = api_key {
+                return authenticate_api_key(service, req, db_conn, &key).await;
+            }
 
-                let token_data = decode::<Claims>(&token, &key, &Validation::default());
+            if let Some(token) = token {
+                let token_data = jwt::decode_access_token(&token);
 
                 match token_data {
                     Ok(token) => {
                         // Extract user_id from the token
-                        let user_id = token.claims.sub.parse::<i32>().unwrap();
+                        let user_id = match token.claims.sub.parse::<i32>() {
+                            Ok(user_id) => user_id,
+                            Err(_) => return Ok(reject(req, HttpResponse::Unauthorized(), "Invalid token")),
+                        };
 
                         // Check if we have a database connection available
                         if let Some(db) = db_conn {
+                            // Reject tokens that were revoked on logout
+                            match revoked_token::is_revoked(db.get_ref(), &token.claims.jti).await {
+                                Ok(false) => {}
+                                Ok(true) => return Ok(reject(req, HttpResponse::Unauthorized(), "Token has been revoked")),
+                                Err(_) => return Ok(reject(req, HttpResponse::InternalServerError(), "Error checking token revocation")),
+                            }
+
+                            // Reject tokens whose session was signed out from another device
+                            if let Some(session_id) = &token.claims.sid {
+                                let ip = login_throttle::client_ip(req.request());
+                                match session::touch(db.get_ref(), session_id, &ip).await {
+                                    Ok(true) => {}
+                                    Ok(false) => return Ok(reject(req, HttpResponse::Unauthorized(), "Session has been revoked")),
+                                    Err(_) => return Ok(reject(req, HttpResponse::InternalServerError(), "Error checking session")),
+                                }
+                            }
+
                             // Query the database to check if the user exists
-                            let user_exists = users::Entity::find_by_id(user_id)
+                            let user_exists = users::Entity::find_active_by_id(user_id)
                                 .one(db.get_ref())
                                 .await;
 
                             match user_exists {
-                                Ok(Some(_)) => {
-                                    // User exists, proceed with the request
-                                    req.extensions_mut().insert(token.claims);
-                                    let res = service.call(req).await?;
-                                    let res = res.map_into_boxed_body();
-                                    Ok(res)
+                                Ok(Some(user)) if user.disabled_at.is_some() => {
+                                    Ok(reject(req, HttpResponse::Forbidden(), "Account has been disabled"))
+                                }
+                                Ok(Some(user)) if user.verified_at.is_none() && email_verification::verification_required() => {
+                                    // Unverified accounts are locked out when verification is enforced
+                                    Ok(reject(req, HttpResponse::Forbidden(), "Email address has not been verified"))
                                 }
+                                Ok(Some(user)) => match UserType::from_str(&user.r#type) {
+                                    Ok(user_type) => {
+                                        let impersonator_id = match &token.claims.act {
+                                            Some(actor) => match impersonation::active_impersonator(db.get_ref(), actor).await {
+                                                Ok(Some(admin_id)) => Some(admin_id),
+                                                Ok(None) => return Ok(reject(req, HttpResponse::Unauthorized(), "Impersonating admin is no longer authorized")),
+                                                Err(_) => return Ok(reject(req, HttpResponse::InternalServerError(), "Error checking user in the database")),
+                                            },
+                                            None => None,
+                                        };
+
+                                        // User exists, proceed with the request
+                                        let jti = token.claims.jti.clone();
+                                        let method = req.method().to_string();
+                                        let path = req.path().to_string();
+                                        req.extensions_mut().insert(token.claims);
+                                        req.extensions_mut().insert(AuthenticatedUser { user, user_type, impersonator_id });
+                                        let result = service.call(req).await;
+
+                                        // Everything done under impersonation is recorded, including refused and
+                                        // failed requests, without holding up the response
+                                        if let Some(admin_id) = impersonator_id {
+                                            let status = match &result {
+                                                Ok(res) => res.status(),
+                                                Err(e) => e.as_response_error().status_code(),
+                                            }
+                                            .as_u16();
+                                            let db = db.get_ref().clone();
+                                            actix_web::rt::spawn(async move {
+                                                if let Err(e) = impersonation::record(&db, admin_id, user_id, &jti, &method, &path, status).await {
+                                                    log::error!("Error recording impersonated request: {}", e);
+                                                }
+                                            });
+                                        }
+
+                                        Ok(result?.map_into_boxed_body())
+                                    }
+                                    Err(_) => Ok(reject(req, HttpResponse::Forbidden(), "Invalid user type")),
+                                },
                                 Ok(None) => {
                                     // User does not exist, return Unauthorized
-                                    let res = HttpResponse::Unauthorized()
-                                        .json(ApiResponse::<()> {
-                                            success: false,
-                                            message: "User does not exist".to_string(),
-                                            data: None,
-                                        });
-                                    Ok(req.into_response(res.map_into_boxed_body()))
+                                    Ok(reject(req, HttpResponse::Unauthorized(), "User does not exist"))
                                 }
                                 Err(_) => {
                                     // Database query error
-                                    let res = HttpResponse::InternalServerError()
-                                        .json(ApiResponse::<()> {
-                                            success: false,
-                                            message: "Error checking user in the database".to_string(),
-                                            data: None,
-                                        });
-                                    Ok(req.into_response(res.map_into_boxed_body()))
+                                    Ok(reject(req, HttpResponse::InternalServerError(), "Error checking user in the database"))
                                 }
                             }
                         } else {
                             // No database connection available
-                            let res = HttpResponse::InternalServerError()
-                                .json(ApiResponse::<()> {
-                                    success: false,
-                                    message: "Database connection not available".to_string(),
-                                    data: None,
-                                });
-                            Ok(req.into_response(res.map_into_boxed_body()))
+                            Ok(reject(req, HttpResponse::InternalServerError(), "Database connection not available"))
                         }
                     }
                     Err(_) => {
                         // Invalid token
-                        let res = HttpResponse::Unauthorized()
-                            .json(ApiResponse::<()> {
-                                success: false,
-                                message: "Invalid token".to_string(),
-                                data: None,
-                            });
-                        Ok(req.into_response(res.map_into_boxed_body()))
+                        Ok(reject(req, HttpResponse::Unauthorized(), "Invalid token"))
                     }
                 }
             } else {
                 // No token provided in header or query params
-                let res = HttpResponse::Unauthorized()
-                    .json(ApiResponse::<()> {
-                        success: false,
-                        message: "No token provided".to_string(),
-                        data: None,
-                    });
-                Ok(req.into_response(res.map_into_boxed_body()))
+                Ok(reject(req, HttpResponse::Unauthorized(), "No token provided"))
             }
         })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::{Service, ServiceResponse};
+    use actix_web::error::ErrorInternalServerError;
+    use actix_web::test::{init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use sea_orm::{DatabaseConnection, EntityTrait, QueryOrder};
+    use std::time::Duration;
+    use super::{ApiKeyScopes, AuthMiddleware};
+    use crate::auth::jwt;
+    use crate::entities::impersonation_logs;
+    use crate::enums::user_type::UserType;
+    use crate::test_support;
+
+    fn scopes(scopes: &[&str]) -> ApiKeyScopes {
+        ApiKeyScopes {
+            key_id: 1,
+            name: "integration".to_string(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn has_matches_granted_scopes_exactly() {
+        let key = scopes(&["users:read", "exams:write"]);
+        assert!(key.has("users:read"));
+        assert!(key.has("exams:write"));
+        assert!(!key.has("users:write"));
+        assert!(!key.has("users"));
+        assert!(!key.has("users:read:all"));
+        assert!(!key.has(""));
+    }
+
+    #[test]
+    fn has_grants_nothing_without_scopes() {
+        assert!(!scopes(&[]).has("users:read"));
+    }
+
+    /// Wait for the audit entries that are written in the background
+    async fn audit_trail(db: &DatabaseConnection, expected: usize) -> Vec<impersonation_logs::Model> {
+        for _ in 0..100 {
+            let entries = impersonation_logs::Entity::find()
+                .order_by_asc(impersonation_logs::Column::Id)
+                .all(db)
+                .await
+                .unwrap();
+            if entries.len() >= expected {
+                return entries;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("expected {} impersonation log entries", expected);
+    }
+
+    #[actix_web::test]
+    async fn impersonated_requests_are_audited_even_when_they_fail() {
+        let db = test_support::database().await;
+        let admin = test_support::create_user(&db, UserType::Admin, "auditor@example.com").await;
+        let member = test_support::create_user(&db, UserType::Member, "audited@example.com").await;
+        let (token, _) = jwt::encode_impersonation_token(member.id, admin.id).unwrap();
+
+        let app = init_service(
+            App::new().app_data(web::Data::new(db.clone())).service(
+                web::scope("/api")
+                    .wrap(AuthMiddleware)
+                    .route("/ok", web::get().to(HttpResponse::Ok))
+                    .service(
+                        web::resource("/failing")
+                            .wrap_fn(|_, _| async { Err::<ServiceResponse, _>(ErrorInternalServerError("failing")) })
+                            .route(web::get().to(HttpResponse::Ok)),
+                    ),
+            ),
+        )
+        .await;
+
+        for uri in ["/api/ok", "/api/failing"] {
+            let req = TestRequest::get()
+                .uri(uri)
+                .insert_header(("Authorization", format!("Bearer {}", token)))
+                .to_request();
+            let result = app.call(req).await;
+            assert_eq!(result.is_err(), uri == "/api/failing");
+        }
+
+        let entries = audit_trail(&db, 2).await;
+        let recorded: Vec<(&str, i32)> = entries.iter().map(|entry| (entry.path.as_str(), entry.status_code)).collect();
+        assert_eq!(recorded, [("/api/ok", 200), ("/api/failing", 500)]);
+        assert!(entries.iter().all(|entry| entry.admin_id == admin.id && entry.user_id == member.id));
+    }
+}
@@ -0,0 +1,77 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, HttpResponse,
+};
+use futures::future::{ok, Ready};
+use futures::Future;
+use std::task::{Context, Poll};
+use std::pin::Pin;
+use std::rc::Rc;
+use crate::http::middlewares::auth::AuthenticatedUser;
+use crate::http::response::ApiResponse;
+
+/// Refuses requests made with an impersonation token, for routes that touch
+/// credentials or act in the user's name. Must be nested inside `AuthMiddleware`.
+pub struct DenyImpersonation;
+
+pub struct DenyImpersonationService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for DenyImpersonation
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = DenyImpersonationService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(DenyImpersonationService {
+            service: Rc::new(service),
+        })
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for DenyImpersonationService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let impersonating = req
+            .extensions()
+            .get::<AuthenticatedUser>()
+            .is_some_and(|auth| auth.impersonator_id.is_some());
+
+        Box::pin(async move {
+            if !impersonating {
+                let res = service.call(req).await?;
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let res = HttpResponse::Forbidden()
+                .json(ApiResponse::<()> {
+                    success: false,
+                    message: "This action is not available while impersonating a user".to_string(),
+                    data: None,
+                });
+            Ok(req.into_response(res.map_into_boxed_body()))
+        })
+    }
+}
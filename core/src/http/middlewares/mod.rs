@@ -1 +1,4 @@
-pub mod auth;
\ No newline at end of file
+pub mod auth;
+pub mod require_role;
+pub mod require_scope;
+pub mod deny_impersonation;
\ No newline at end of file
@@ -0,0 +1,85 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, HttpResponse,
+};
+use futures::future::{ok, Ready};
+use futures::Future;
+use std::task::{Context, Poll};
+use std::pin::Pin;
+use std::rc::Rc;
+use crate::enums::user_type::UserType;
+use crate::http::middlewares::auth::{api_key_permitted, AuthenticatedUser};
+use crate::http::response::ApiResponse;
+
+/// Only lets requests through when the authenticated user has one of the given types.
+/// Must be nested inside `AuthMiddleware`, which stores the user on the request.
+///
+/// ```ignore
+/// web::scope("/admin").wrap(RequireRole(&[UserType::Admin]))
+/// ```
+pub struct RequireRole(pub &'static [UserType]);
+
+pub struct RequireRoleService<S> {
+    service: Rc<S>,
+    roles: &'static [UserType],
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = RequireRoleService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequireRoleService {
+            service: Rc::new(service),
+            roles: self.0,
+        })
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for RequireRoleService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let allowed = api_key_permitted(&req.extensions())
+            && req
+                .extensions()
+                .get::<AuthenticatedUser>()
+                .is_some_and(|auth| self.roles.contains(&auth.user_type));
+
+        Box::pin(async move {
+            if allowed {
+                let res = service.call(req).await?;
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let res = HttpResponse::Forbidden()
+                .json(ApiResponse::<()> {
+                    success: false,
+                    message: "You do not have permission to access this resource".to_string(),
+                    data: None,
+                });
+            Ok(req.into_response(res.map_into_boxed_body()))
+        })
+    }
+}
@@ -0,0 +1,94 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpMessage, HttpResponse,
+};
+use futures::future::{ok, Ready};
+use futures::Future;
+use std::task::{Context, Poll};
+use std::pin::Pin;
+use std::rc::Rc;
+use crate::http::middlewares::auth::{ApiKeyScopes, ScopeGranted};
+use crate::http::response::ApiResponse;
+
+/// Opens a route to API keys that carry the given scope. Requests made with a user
+/// token pass straight through and are left to `RequireRole` and the handler.
+///
+/// actix runs the last `wrap` first, so add it after any `RequireRole` on the same route:
+///
+/// ```ignore
+/// web::resource("/user/{id}/details")
+///     .wrap(RequireRole(&[UserType::Proctor, UserType::Admin]))
+///     .wrap(RequireScope("users:read"))
+/// ```
+pub struct RequireScope(pub &'static str);
+
+pub struct RequireScopeService<S> {
+    service: Rc<S>,
+    scope: &'static str,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequireScope
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = RequireScopeService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequireScopeService {
+            service: Rc::new(service),
+            scope: self.0,
+        })
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for RequireScopeService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let scope = self.scope;
+        let granted = req
+            .extensions()
+            .get::<ApiKeyScopes>()
+            .map(|api_key| api_key.has(scope));
+
+        Box::pin(async move {
+            match granted {
+                Some(false) => {
+                    let res = HttpResponse::Forbidden()
+                        .json(ApiResponse::<()> {
+                            success: false,
+                            message: format!("API key is missing the '{}' scope", scope),
+                            data: None,
+                        });
+                    Ok(req.into_response(res.map_into_boxed_body()))
+                }
+                granted => {
+                    if granted.is_some() {
+                        req.extensions_mut().insert(ScopeGranted);
+                    }
+                    let res = service.call(req).await?;
+                    Ok(res.map_into_boxed_body())
+                }
+            }
+        })
+    }
+}
@@ -3,4 +3,5 @@ pub mod controllers;
 pub mod config;
 pub mod middlewares;
 pub mod response;
-pub mod socket;
\ No newline at end of file
+pub mod socket;
+pub mod validation;
\ No newline at end of file
@@ -1,4 +1,5 @@
 use serde::Serialize;
+use std::collections::BTreeMap;
 
 #[derive(Serialize)]
 pub struct ApiResponse<T> {
@@ -6,3 +7,6 @@ pub struct ApiResponse<T> {
     pub message: String,
     pub data: Option<T>,
 }
+
+/// Validation messages keyed by request field
+pub type FieldErrors = BTreeMap<String, Vec<String>>;
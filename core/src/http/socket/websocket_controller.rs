@@ -1,22 +1,27 @@
 use crate::http::socket::websocket_server::WebSocketServer;
-use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_web::{web, Error, HttpRequest, HttpResponse, Responder};
 use actix_web_actors::ws::{Message, ProtocolError, WebsocketContext};
 use tokio::sync::mpsc;
 use serde_json::Value;
-use sea_orm::{DatabaseConnection, EntityTrait, Set};
+use sea_orm::{ActiveModelTrait, DatabaseConnection, EntityTrait, Set};
 use std::sync::{Arc, Mutex};
 use actix::{Actor, ActorContext, AsyncContext, Handler, StreamHandler};
 use actix_web_actors::ws;
 use uuid::Uuid;
 use actix::fut::wrap_future;
 use sea_orm::QueryFilter;
-use crate::http::controllers::base_controller::{BaseController, Controller};
 use sea_orm::ColumnTrait;
 use std::collections::HashMap;
 use lazy_static::lazy_static;
 use actix::Message as ActixMessage;
-use crate::entities::{chat_rooms, users};
+use crate::entities::{chat_room_members, chat_rooms, users};
 use crate::enums::user_type::UserType;
+use crate::auth::ws_ticket;
+use crate::http::controllers::base_controller::{BaseController, Controller};
+use crate::http::middlewares::auth::AuthenticatedUser;
+use serde::Deserialize;
+use std::str::FromStr;
+use chrono::Utc;
 
 lazy_static! {
     static ref WS_SERVERS: Arc<Mutex<HashMap<String, Arc<Mutex<WebSocketServer>>>>> =
@@ -185,24 +190,94 @@ async fn save_chat_room(room_id: &str, db: &DatabaseConnection) {
     chat_rooms::Entity::insert(room).exec(db).await.unwrap();
 }
 
+/// Remember that the user joined the room, so it shows up in their data export
+async fn record_membership(room_id: &str, user_id: i32, user_type: &UserType, db: &DatabaseConnection) {
+    let existing = chat_room_members::Entity::find()
+        .filter(chat_room_members::Column::RoomId.eq(room_id))
+        .filter(chat_room_members::Column::UserId.eq(user_id))
+        .one(db)
+        .await;
+
+    let result = match existing {
+        Ok(Some(member)) => {
+            let mut member: chat_room_members::ActiveModel = member.into();
+            member.last_joined_at = Set(Utc::now());
+            member.update(db).await.map(|_| ())
+        }
+        Ok(None) => {
+            let member = chat_room_members::ActiveModel {
+                room_id: Set(room_id.to_string()),
+                user_id: Set(user_id),
+                role: Set(user_type.to_string()),
+                first_joined_at: Set(Utc::now()),
+                last_joined_at: Set(Utc::now()),
+                ..Default::default()
+            };
+            chat_room_members::Entity::insert(member).exec(db).await.map(|_| ())
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = result {
+        log::error!("Error recording membership of user {} in room {}: {}", user_id, room_id, e);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WsTicketRequest {
+    pub room_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct WsConnectQuery {
+    /// Optional so a missing ticket gets the usual 401 body instead of the extractor's 400
+    pub ticket: Option<String>,
+}
+
+/// Issue a single-use ticket for opening a room's WebSocket, since browsers
+/// cannot send an Authorization header on the upgrade request
+pub async fn create_ws_ticket(
+    auth: AuthenticatedUser,
+    form: web::Json<WsTicketRequest>,
+) -> impl Responder {
+    let ticket = ws_ticket::issue(auth.user.id, &form.room_id);
+    Controller::created(
+        "WebSocket ticket issued.",
+        serde_json::json!({ "ticket": ticket, "expires_in": ws_ticket::ticket_ttl().num_seconds() }),
+    )
+}
+
+/// Registered outside `AuthMiddleware`: the upgrade is authorised by a ticket from `create_ws_ticket`
 pub async fn websocket_index(
     req: HttpRequest,
+    query: web::Query<WsConnectQuery>,
     stream: web::Payload,
     db_pool: web::Data<DatabaseConnection>,
 ) -> Result<HttpResponse, Error> {
     let room_id = req.match_info().get("room_id").unwrap_or("unknown").to_string();
 
-    let claims = Controller::get_claims(&req);
-    if claims.is_none() {
-        return Ok(Controller::unauthorized("Unauthorized"));
+    let ticket = match &query.ticket {
+        Some(ticket) => ticket,
+        None => return Ok(Controller::unauthorized("A WebSocket ticket is required")),
+    };
+    let user_id = match ws_ticket::redeem(ticket, &room_id) {
+        Some(user_id) => user_id,
+        None => return Ok(Controller::unauthorized("Invalid or expired WebSocket ticket")),
+    };
+    // The ticket may have been issued before the account was deleted or disabled
+    let user = match users::Entity::find_active_by_id(user_id).one(db_pool.get_ref()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Ok(Controller::not_found("User not found")),
+        Err(_) => return Ok(Controller::internal_server_error("Error retrieving user")),
+    };
+    if let Some(err_response) = Controller::reject_disabled(&user) {
+        return Ok(err_response);
     }
+    let user_type = match UserType::from_str(&user.r#type) {
+        Ok(user_type) => user_type,
+        Err(_) => return Ok(Controller::forbidden("Invalid user type")),
+    };
 
-    let user_id: i32 = claims.unwrap().sub.parse().unwrap();
-    let user = users::Entity::find()
-        .filter(users::Column::Id.eq(user_id))
-        .one(db_pool.get_ref())
-        .await
-        .ok();
     println!(
         "🌐 WebSocket connection for room ID: {}, requester ID: {}",
         room_id, user_id
@@ -232,26 +307,51 @@ pub async fn websocket_index(
     };
 
     let connection_id = Uuid::new_v4().to_string();
-    let user_type = user.map(|r| r.unwrap().r#type).unwrap().to_string();
 
     println!(
         "👤 Establishing WebSocket for user_type: {}, user_id: {}, connection_id: {}",
         user_type, user_id, connection_id
     );
 
-    if user_type != UserType::Member.to_string() && user_type != UserType::Proctor.to_string() {
+    if user_type != UserType::Member && user_type != UserType::Proctor {
         println!("❌ Invalid user type: {}", user_type);
         return Ok(HttpResponse::BadRequest().body("Invalid user type"));
     }
 
+    // Bookkeeping for data exports only, so it must not hold up the upgrade
+    let (member_room_id, member_type, db) = (room_id.clone(), user_type.clone(), db_pool.get_ref().clone());
+    actix_web::rt::spawn(async move {
+        record_membership(&member_room_id, user_id, &member_type, &db).await;
+    });
+
     let actor = WebSocketActor::new(
         ws_server,
         connection_id,
         room_id,
-        user_type,
+        user_type.to_string(),
         user_id,
         db_pool
     );
 
     ws::start(actor, &req, stream)
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use crate::http::config::config;
+    use crate::test_support::{self, call_json};
+
+    #[actix_web::test]
+    async fn upgrades_without_a_valid_ticket_get_the_standard_error_body() {
+        let db = test_support::database().await;
+        let app = test::init_service(App::new().app_data(web::Data::new(db.clone())).configure(config)).await;
+
+        for uri in ["/api/ws/chat/room-1", "/api/ws/chat/room-1?ticket=unknown"] {
+            let (status, body) = call_json(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+            assert_eq!(body["success"], false);
+        }
+    }
+}
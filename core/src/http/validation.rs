@@ -0,0 +1,80 @@
+use serde::Deserialize;
+use crate::http::response::FieldErrors;
+
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_EMAIL_LENGTH: usize = 254;
+const MIN_PHONE_DIGITS: usize = 7;
+const MAX_PHONE_DIGITS: usize = 15;
+
+/// Profile fields a user or an admin may change; absent fields are left as they are
+#[derive(Deserialize)]
+pub struct ProfileUpdate {
+    pub email: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub phone: Option<String>,
+}
+
+impl ProfileUpdate {
+    /// Check the fields that are present, returning the errors of each invalid one
+    pub fn validate(&self) -> FieldErrors {
+        let mut errors = FieldErrors::new();
+
+        for (field, value) in [("first_name", &self.first_name), ("last_name", &self.last_name)] {
+            if let Some(message) = value.as_deref().and_then(|value| name_error(field, value)) {
+                errors.insert(field.to_string(), vec![message]);
+            }
+        }
+        if let Some(message) = self.email.as_deref().and_then(email_error) {
+            errors.insert("email".to_string(), vec![message]);
+        }
+        if let Some(message) = self.phone.as_deref().and_then(phone_error) {
+            errors.insert("phone".to_string(), vec![message]);
+        }
+
+        errors
+    }
+}
+
+fn name_error(field: &str, value: &str) -> Option<String> {
+    let label = field.replace('_', " ");
+    let value = value.trim();
+    if value.is_empty() {
+        Some(format!("The {} field cannot be empty.", label))
+    } else if value.chars().count() > MAX_NAME_LENGTH {
+        Some(format!("The {} may not be longer than {} characters.", label, MAX_NAME_LENGTH))
+    } else {
+        None
+    }
+}
+
+fn email_error(value: &str) -> Option<String> {
+    let value = value.trim();
+    let valid = value.len() <= MAX_EMAIL_LENGTH
+        && !value.chars().any(char::is_whitespace)
+        && value.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty() && !domain.contains('@') && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+        });
+
+    match valid {
+        true => None,
+        false => Some("The email must be a valid email address.".to_string()),
+    }
+}
+
+/// Digits with an optional leading `+`; spaces, dashes and brackets are allowed as separators
+fn phone_error(value: &str) -> Option<String> {
+    let value = value.trim();
+    let body = value.strip_prefix('+').unwrap_or(value);
+    let digits = body.chars().filter(char::is_ascii_digit).count();
+    let valid = body.chars().all(|c| c.is_ascii_digit() || matches!(c, ' ' | '-' | '(' | ')'))
+        && (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits);
+
+    match valid {
+        true => None,
+        false => Some(format!(
+            "The phone must be a phone number of {} to {} digits.",
+            MIN_PHONE_DIGITS, MAX_PHONE_DIGITS
+        )),
+    }
+}
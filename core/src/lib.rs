@@ -4,4 +4,10 @@ pub mod http;
 mod enums;
 mod encryption;
 mod web3;
-mod traits;
\ No newline at end of file
+mod traits;
+mod auth;
+mod mail;
+mod sms;
+
+#[cfg(test)]
+mod test_support;
\ No newline at end of file
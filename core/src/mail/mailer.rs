@@ -0,0 +1,89 @@
+use actix_web::web;
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{FileTransport, Message, SmtpTransport, Transport};
+use std::env;
+use std::fs;
+
+const DEFAULT_MAIL_DIR: &str = "./storage/mail";
+
+/// Where outgoing mail is delivered, selected with MAIL_TRANSPORT
+pub enum MailTransport {
+    /// Deliver through the SMTP_* server
+    Smtp,
+    /// Write each message as an .eml file into MAIL_FILE_DIR
+    File(String),
+    /// Only write the message to the application log
+    Log,
+}
+
+impl MailTransport {
+    /// Read the transport from the environment, defaulting to SMTP
+    pub fn from_env() -> Self {
+        match env::var("MAIL_TRANSPORT").unwrap_or_default().to_lowercase().as_str() {
+            "file" => MailTransport::File(
+                env::var("MAIL_FILE_DIR").unwrap_or_else(|_| DEFAULT_MAIL_DIR.to_string()),
+            ),
+            "log" => MailTransport::Log,
+            _ => MailTransport::Smtp,
+        }
+    }
+}
+
+pub struct Mailer;
+
+impl Mailer {
+    /// Send a plain text email to a single recipient
+    pub async fn send(to: &str, subject: &str, body: String) -> Result<(), String> {
+        let from = env::var("SMTP_FROM").map_err(|_| "SMTP_FROM not set".to_string())?;
+        let message = Message::builder()
+            .from(from.parse().map_err(|e| format!("Invalid SMTP_FROM address: {}", e))?)
+            .to(to.parse().map_err(|e| format!("Invalid recipient address: {}", e))?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| format!("Failed to build email: {}", e))?;
+
+        // lettre's transports are blocking, so keep them off the async workers
+        web::block(move || Self::deliver(MailTransport::from_env(), message))
+            .await
+            .map_err(|e| format!("Mail delivery task failed: {}", e))?
+    }
+
+    fn deliver(transport: MailTransport, message: Message) -> Result<(), String> {
+        match transport {
+            MailTransport::Smtp => {
+                let server = env::var("SMTP_SERVER").map_err(|_| "SMTP_SERVER not set".to_string())?;
+                let port = env::var("SMTP_PORT")
+                    .ok()
+                    .and_then(|port| port.parse::<u16>().ok())
+                    .unwrap_or(587);
+                let credentials = Credentials::new(
+                    env::var("SMTP_USERNAME").unwrap_or_default(),
+                    env::var("SMTP_PASSWORD").unwrap_or_default(),
+                );
+
+                let mailer = SmtpTransport::starttls_relay(&server)
+                    .map_err(|e| format!("Invalid SMTP server: {}", e))?
+                    .port(port)
+                    .credentials(credentials)
+                    .build();
+                mailer.send(&message).map_err(|e| format!("SMTP delivery failed: {}", e))?;
+            }
+            MailTransport::File(dir) => {
+                fs::create_dir_all(&dir).map_err(|e| format!("Failed to create mail directory: {}", e))?;
+                FileTransport::new(&dir)
+                    .send(&message)
+                    .map_err(|e| format!("File delivery failed: {}", e))?;
+            }
+            MailTransport::Log => {
+                log::info!(
+                    "Outgoing mail:\n{}",
+                    String::from_utf8_lossy(&message.formatted())
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
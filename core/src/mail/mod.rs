@@ -0,0 +1 @@
+pub mod mailer;
\ No newline at end of file
@@ -0,0 +1 @@
+pub mod sender;
\ No newline at end of file
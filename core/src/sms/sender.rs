@@ -0,0 +1,51 @@
+use serde_json::json;
+use std::env;
+
+/// Where outgoing text messages are delivered, selected with SMS_TRANSPORT
+pub enum SmsTransport {
+    /// POST `{"to", "message"}` to SMS_WEBHOOK_URL, e.g. a gateway in front of the SMS provider
+    Webhook(String),
+    /// Only write the message to the application log
+    Log,
+}
+
+impl SmsTransport {
+    /// Read the transport from the environment, defaulting to the log
+    pub fn from_env() -> Result<Self, String> {
+        match env::var("SMS_TRANSPORT").unwrap_or_default().to_lowercase().as_str() {
+            "webhook" => env::var("SMS_WEBHOOK_URL")
+                .map(SmsTransport::Webhook)
+                .map_err(|_| "SMS_WEBHOOK_URL not set".to_string()),
+            _ => Ok(SmsTransport::Log),
+        }
+    }
+}
+
+pub struct SmsSender;
+
+impl SmsSender {
+    /// Send a text message to a single phone number
+    pub async fn send(to: &str, message: String) -> Result<(), String> {
+        match SmsTransport::from_env()? {
+            SmsTransport::Webhook(url) => {
+                let mut request = reqwest::Client::new()
+                    .post(&url)
+                    .json(&json!({ "to": to, "message": message }));
+                if let Ok(token) = env::var("SMS_WEBHOOK_TOKEN") {
+                    request = request.bearer_auth(token);
+                }
+
+                request
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| format!("SMS delivery failed: {}", e))?;
+            }
+            SmsTransport::Log => {
+                log::info!("Outgoing SMS to {}:\n{}", to, message);
+            }
+        }
+
+        Ok(())
+    }
+}
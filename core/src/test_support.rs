@@ -0,0 +1,134 @@
+//! Shared setup for tests that drive the HTTP app end to end against an in-memory SQLite database
+
+use actix_http::Request;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::test;
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ConnectOptions, ConnectionTrait, Database, DatabaseConnection, EntityTrait, Schema, Set, Statement,
+};
+use std::env;
+use std::sync::Once;
+use crate::auth::password;
+use crate::entities::*;
+use crate::enums::user_type::UserType;
+
+pub const FRONTEND_URL: &str = "http://localhost:3000";
+pub const PASSWORD: &str = "correct horse battery staple";
+
+static ENV: Once = Once::new();
+
+/// Configure the environment the app reads; every test process shares one copy
+pub fn init_env() {
+    ENV.call_once(|| {
+        env::set_var("SECRET_KEY", "test-secret-key-that-is-long-enough");
+        // base64 of 32 bytes, as `openssl rand -base64 32` produces
+        env::set_var("APP_KEY", "MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTIzNDU2Nzg5MDE=");
+        env::set_var("FRONTEND_URL", FRONTEND_URL);
+        env::set_var("MAIL_TRANSPORT", "log");
+        env::set_var("SMTP_FROM", "proctodot <noreply@proctodot.test>");
+        env::set_var("SMS_TRANSPORT", "log");
+        env::set_var("MFA_REQUIRED_USER_TYPES", "proctor,admin");
+        // Keep Argon2 cheap so login-heavy tests stay fast
+        env::set_var("ARGON2_MEMORY_KIB", "1024");
+        env::set_var("ARGON2_ITERATIONS", "1");
+    });
+}
+
+/// A fresh database holding every table the entities describe
+pub async fn database() -> DatabaseConnection {
+    init_env();
+
+    // One connection, since every SQLite in-memory connection is a separate database
+    let mut options = ConnectOptions::new("sqlite::memory:".to_string());
+    options.max_connections(1).sqlx_logging(false);
+    let db = Database::connect(options).await.expect("SQLite connects");
+
+    let backend = db.get_database_backend();
+    let schema = Schema::new(backend);
+    macro_rules! create_tables {
+        ($($entity:ident),* $(,)?) => {
+            $(
+                db.execute(backend.build(&schema.create_table_from_entity($entity::Entity)))
+                    .await
+                    .expect(concat!("create ", stringify!($entity)));
+            )*
+        };
+    }
+    // chat_rooms is left out: its string primary key is declared auto-increment, which SQLite rejects
+    create_tables!(
+        users, passwords, user_wallet, refresh_tokens, revoked_tokens, password_resets, user_mfa,
+        mfa_recovery_codes, passkey_credentials, user_identities, wallet_challenges, login_attempts,
+        lockout_events, invitations, api_keys, impersonation_logs, sessions, phone_changes, chat_room_members,
+        data_exports,
+    );
+    // Composite unique indexes that the entities cannot describe
+    db.execute(Statement::from_string(
+        backend,
+        "CREATE UNIQUE INDEX idx_login_attempts_key ON login_attempts (key_type, key_value)".to_string(),
+    ))
+    .await
+    .expect("create login_attempts index");
+
+    db
+}
+
+/// Insert a verified user of the given type whose password is `PASSWORD`
+pub async fn create_user(db: &DatabaseConnection, user_type: UserType, email: &str) -> users::Model {
+    let user = users::ActiveModel {
+        chain: Set("polkadot".to_string()),
+        email: Set(email.to_string()),
+        first_name: Set("Test".to_string()),
+        last_name: Set("User".to_string()),
+        phone: Set("+10000000000".to_string()),
+        r#type: Set(user_type.to_string()),
+        verified_at: Set(Some(Utc::now())),
+        created_at: Set(Utc::now()),
+        updated_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .expect("insert user");
+
+    passwords::Entity::insert(passwords::ActiveModel {
+        user_id: Set(user.id),
+        password_hash: Set(password::hash_password(PASSWORD).await.expect("hash password")),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    })
+    .exec(db)
+    .await
+    .expect("insert password");
+
+    user
+}
+
+/// Send a request and decode the JSON body, or `Null` when there is none
+pub async fn call_json<S, B>(app: &S, req: Request) -> (StatusCode, serde_json::Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let response = test::call_service(app, req).await;
+    let status = response.status();
+    let body = test::read_body(response).await;
+    (status, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+}
+
+/// Sign in with `PASSWORD` and return the response data
+pub async fn login<S, B>(app: &S, email: &str) -> serde_json::Value
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody,
+{
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(serde_json::json!({ "email": email, "password": PASSWORD }))
+        .to_request();
+    let (status, body) = call_json(app, req).await;
+    assert_eq!(status, StatusCode::OK, "login failed: {}", body);
+    body["data"].clone()
+}
@@ -29,4 +29,32 @@ impl WalletHandler {
             address,
         }
     }
+
+    /// Whether the string is a well-formed SS58 address
+    pub fn is_valid_address(address: &str) -> bool {
+        // SS58 addresses are at most 50 characters, so anything longer is rejected before decoding
+        address.len() <= 50 && sr25519::Public::from_ss58check(address).is_ok()
+    }
+
+    /// Verify an sr25519 signature over a message for an SS58 address.
+    /// polkadot-js `signRaw` wraps the payload in <Bytes> tags, so both forms are accepted.
+    pub fn verify_signature(address: &str, message: &str, signature: &str) -> bool {
+        let public = match sr25519::Public::from_ss58check(address) {
+            Ok(public) => public,
+            Err(_) => return false,
+        };
+
+        let signature_bytes = match hex::decode(signature.trim_start_matches("0x")) {
+            Ok(bytes) => bytes,
+            Err(_) => return false,
+        };
+        let signature = match sr25519::Signature::try_from(signature_bytes.as_slice()) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+
+        let wrapped = format!("<Bytes>{}</Bytes>", message);
+        sr25519::Pair::verify(&signature, message.as_bytes(), &public)
+            || sr25519::Pair::verify(&signature, wrapped.as_bytes(), &public)
+    }
 }
\ No newline at end of file
@@ -4,6 +4,26 @@ mod m20250514_154701_create_users_table;
 mod m20250514_194614_create_passwords_table;
 mod m20250514_200035_create_user_wallets_table;
 mod m20250515_133221_create_chat_rooms_table;
+mod m20261018_090000_create_refresh_tokens_table;
+mod m20261018_100000_create_revoked_tokens_table;
+mod m20261018_110000_add_verified_at_to_users_table;
+mod m20261018_120000_create_password_resets_table;
+mod m20261018_130000_create_user_mfa_table;
+mod m20261018_130100_create_mfa_recovery_codes_table;
+mod m20261018_140000_create_passkey_credentials_table;
+mod m20261018_150000_create_user_identities_table;
+mod m20261018_160000_create_wallet_challenges_table;
+mod m20261018_170000_create_login_attempts_table;
+mod m20261018_170100_create_lockout_events_table;
+mod m20261018_180000_create_invitations_table;
+mod m20261018_190000_create_api_keys_table;
+mod m20261018_200000_create_impersonation_logs_table;
+mod m20261018_210000_create_sessions_table;
+mod m20261018_220000_add_disabled_at_to_users_table;
+mod m20261018_230000_scope_user_email_uniqueness_to_live_rows;
+mod m20261018_233000_create_phone_changes_table;
+mod m20261018_234000_create_chat_room_members_table;
+mod m20261018_235000_create_data_exports_table;
 
 pub struct Migrator;
 
@@ -15,6 +35,26 @@ impl MigratorTrait for Migrator {
             Box::new(m20250514_194614_create_passwords_table::Migration),
             Box::new(m20250514_200035_create_user_wallets_table::Migration),
             Box::new(m20250515_133221_create_chat_rooms_table::Migration),
+            Box::new(m20261018_090000_create_refresh_tokens_table::Migration),
+            Box::new(m20261018_100000_create_revoked_tokens_table::Migration),
+            Box::new(m20261018_110000_add_verified_at_to_users_table::Migration),
+            Box::new(m20261018_120000_create_password_resets_table::Migration),
+            Box::new(m20261018_130000_create_user_mfa_table::Migration),
+            Box::new(m20261018_130100_create_mfa_recovery_codes_table::Migration),
+            Box::new(m20261018_140000_create_passkey_credentials_table::Migration),
+            Box::new(m20261018_150000_create_user_identities_table::Migration),
+            Box::new(m20261018_160000_create_wallet_challenges_table::Migration),
+            Box::new(m20261018_170000_create_login_attempts_table::Migration),
+            Box::new(m20261018_170100_create_lockout_events_table::Migration),
+            Box::new(m20261018_180000_create_invitations_table::Migration),
+            Box::new(m20261018_190000_create_api_keys_table::Migration),
+            Box::new(m20261018_200000_create_impersonation_logs_table::Migration),
+            Box::new(m20261018_210000_create_sessions_table::Migration),
+            Box::new(m20261018_220000_add_disabled_at_to_users_table::Migration),
+            Box::new(m20261018_230000_scope_user_email_uniqueness_to_live_rows::Migration),
+            Box::new(m20261018_233000_create_phone_changes_table::Migration),
+            Box::new(m20261018_234000_create_chat_room_members_table::Migration),
+            Box::new(m20261018_235000_create_data_exports_table::Migration),
         ];
 
         // Ensure the seeder migration is always the last one
@@ -0,0 +1,76 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RevokedTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(RevokedTokens::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    // The `jti` claim of the revoked access token
+                    .col(ColumnDef::new(RevokedTokens::Jti).string().not_null().unique_key())
+                    .col(ColumnDef::new(RevokedTokens::UserId).integer().not_null())
+                    // Entries are pruned once the token would have expired anyway
+                    .col(
+                        ColumnDef::new(RevokedTokens::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RevokedTokens::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_revoked_token_user")
+                            .from(RevokedTokens::Table, RevokedTokens::UserId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_revoked_tokens_expires_at")
+                    .table(RevokedTokens::Table)
+                    .col(RevokedTokens::ExpiresAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RevokedTokens::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum RevokedTokens {
+    Table,
+    Id,
+    Jti,
+    UserId,
+    ExpiresAt,
+    CreatedAt,
+}
@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserMfa::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserMfa::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserMfa::UserId).integer().not_null().unique_key())
+                    // Save the encrypted TOTP secret in the "secret" column
+                    .col(ColumnDef::new(UserMfa::Secret).text().not_null())
+                    // Null while the enrolment is waiting for its first code
+                    .col(
+                        ColumnDef::new(UserMfa::EnabledAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(ColumnDef::new(UserMfa::LastUsedStep).big_integer().null())
+                    .col(
+                        ColumnDef::new(UserMfa::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserMfa::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_user_mfa_user")
+                            .from(UserMfa::Table, UserMfa::UserId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserMfa::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum UserMfa {
+    Table,
+    Id,
+    UserId,
+    Secret,
+    EnabledAt,
+    LastUsedStep,
+    CreatedAt,
+    UpdatedAt,
+}
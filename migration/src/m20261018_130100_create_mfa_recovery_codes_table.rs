@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(MfaRecoveryCodes::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MfaRecoveryCodes::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(MfaRecoveryCodes::UserId).integer().not_null())
+                    // Only the SHA-256 digest of each recovery code is stored
+                    .col(ColumnDef::new(MfaRecoveryCodes::CodeHash).string().not_null())
+                    .col(
+                        ColumnDef::new(MfaRecoveryCodes::UsedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(MfaRecoveryCodes::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_mfa_recovery_code_user")
+                            .from(MfaRecoveryCodes::Table, MfaRecoveryCodes::UserId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MfaRecoveryCodes::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum MfaRecoveryCodes {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    UsedAt,
+    CreatedAt,
+}
@@ -0,0 +1,75 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasskeyCredentials::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PasskeyCredentials::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(PasskeyCredentials::UserId).integer().not_null())
+                    .col(ColumnDef::new(PasskeyCredentials::CredentialId).string().not_null().unique_key())
+                    .col(ColumnDef::new(PasskeyCredentials::Name).string().not_null())
+                    // Serialized passkey with the public key and signature counter
+                    .col(ColumnDef::new(PasskeyCredentials::Passkey).text().not_null())
+                    .col(
+                        ColumnDef::new(PasskeyCredentials::LastUsedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasskeyCredentials::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PasskeyCredentials::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_passkey_credential_user")
+                            .from(PasskeyCredentials::Table, PasskeyCredentials::UserId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PasskeyCredentials::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PasskeyCredentials {
+    Table,
+    Id,
+    UserId,
+    CredentialId,
+    Name,
+    Passkey,
+    LastUsedAt,
+    CreatedAt,
+    UpdatedAt,
+}
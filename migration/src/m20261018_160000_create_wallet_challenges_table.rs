@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WalletChallenges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(WalletChallenges::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(WalletChallenges::Address).string().not_null())
+                    .col(ColumnDef::new(WalletChallenges::Nonce).string().not_null().unique_key())
+                    .col(ColumnDef::new(WalletChallenges::Message).text().not_null())
+                    .col(
+                        ColumnDef::new(WalletChallenges::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WalletChallenges::UsedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(WalletChallenges::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WalletChallenges::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum WalletChallenges {
+    Table,
+    Id,
+    Address,
+    Nonce,
+    Message,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
@@ -0,0 +1,71 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginAttempts::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(LoginAttempts::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(LoginAttempts::KeyType).string_len(16).not_null())
+                    .col(ColumnDef::new(LoginAttempts::KeyValue).string().not_null())
+                    .col(ColumnDef::new(LoginAttempts::FailedCount).integer().not_null().default(0))
+                    .col(
+                        ColumnDef::new(LoginAttempts::LastFailedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LoginAttempts::LockedUntil)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_login_attempts_key")
+                    .table(LoginAttempts::Table)
+                    .col(LoginAttempts::KeyType)
+                    .col(LoginAttempts::KeyValue)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LoginAttempts::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum LoginAttempts {
+    Table,
+    Id,
+    KeyType,
+    KeyValue,
+    FailedCount,
+    LastFailedAt,
+    LockedUntil,
+}
@@ -0,0 +1,79 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(LockoutEvents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(LockoutEvents::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(LockoutEvents::UserId).integer().null())
+                    .col(ColumnDef::new(LockoutEvents::Email).string().not_null())
+                    .col(ColumnDef::new(LockoutEvents::IpAddress).string().not_null())
+                    .col(ColumnDef::new(LockoutEvents::Reason).string_len(16).not_null())
+                    .col(ColumnDef::new(LockoutEvents::FailedCount).integer().not_null())
+                    .col(
+                        ColumnDef::new(LockoutEvents::LockedUntil)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(LockoutEvents::UnlockedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(ColumnDef::new(LockoutEvents::UnlockedBy).integer().null())
+                    .col(
+                        ColumnDef::new(LockoutEvents::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_lockout_event_user")
+                            .from(LockoutEvents::Table, LockoutEvents::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LockoutEvents::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum LockoutEvents {
+    Table,
+    Id,
+    UserId,
+    Email,
+    IpAddress,
+    Reason,
+    FailedCount,
+    LockedUntil,
+    UnlockedAt,
+    UnlockedBy,
+    CreatedAt,
+}
@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImpersonationLogs::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ImpersonationLogs::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ImpersonationLogs::AdminId).integer().not_null())
+                    .col(ColumnDef::new(ImpersonationLogs::UserId).integer().not_null())
+                    .col(ColumnDef::new(ImpersonationLogs::TokenId).string().not_null())
+                    .col(ColumnDef::new(ImpersonationLogs::Method).string_len(16).not_null())
+                    .col(ColumnDef::new(ImpersonationLogs::Path).text().not_null())
+                    .col(ColumnDef::new(ImpersonationLogs::StatusCode).integer().not_null())
+                    .col(
+                        ColumnDef::new(ImpersonationLogs::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_impersonation_log_admin")
+                            .from(ImpersonationLogs::Table, ImpersonationLogs::AdminId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_impersonation_log_user")
+                            .from(ImpersonationLogs::Table, ImpersonationLogs::UserId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_impersonation_logs_token_id")
+                    .table(ImpersonationLogs::Table)
+                    .col(ImpersonationLogs::TokenId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ImpersonationLogs::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ImpersonationLogs {
+    Table,
+    Id,
+    AdminId,
+    UserId,
+    TokenId,
+    Method,
+    Path,
+    StatusCode,
+    CreatedAt,
+}
@@ -0,0 +1,78 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Soft deleted users keep their email, so uniqueness moves from `email` to a
+/// generated column that is NULL for deleted rows. MySQL lets NULLs repeat in a unique index.
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(Users::ActiveEmail)
+                            .string()
+                            .null()
+                            .generated(Expr::cust("IF(`deleted_at` IS NULL, `email`, NULL)"), true),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_users_active_email")
+                    .table(Users::Table)
+                    .col(Users::ActiveEmail)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // The unique key created inline with the users table is named after its column
+        manager
+            .drop_index(Index::drop().name("email").table(Users::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_index(
+                Index::create()
+                    .name("email")
+                    .table(Users::Table)
+                    .col(Users::Email)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(Index::drop().name("idx_users_active_email").table(Users::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::ActiveEmail)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Email,
+    ActiveEmail,
+}
@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PhoneChanges::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PhoneChanges::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(PhoneChanges::UserId).integer().not_null().unique_key())
+                    .col(ColumnDef::new(PhoneChanges::Phone).string().not_null())
+                    .col(ColumnDef::new(PhoneChanges::CodeHash).string().not_null())
+                    .col(
+                        ColumnDef::new(PhoneChanges::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(
+                        ColumnDef::new(PhoneChanges::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(PhoneChanges::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_phone_change_user")
+                            .from(PhoneChanges::Table, PhoneChanges::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PhoneChanges::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PhoneChanges {
+    Table,
+    Id,
+    UserId,
+    Phone,
+    CodeHash,
+    Attempts,
+    ExpiresAt,
+    CreatedAt,
+}
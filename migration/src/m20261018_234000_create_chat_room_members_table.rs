@@ -0,0 +1,87 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+use crate::m20250515_133221_create_chat_rooms_table::ChatRooms;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ChatRoomMembers::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ChatRoomMembers::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(ChatRoomMembers::RoomId).string().not_null())
+                    .col(ColumnDef::new(ChatRoomMembers::UserId).integer().not_null())
+                    .col(ColumnDef::new(ChatRoomMembers::Role).string().not_null())
+                    .col(
+                        ColumnDef::new(ChatRoomMembers::FirstJoinedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ChatRoomMembers::LastJoinedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_chat_room_member_room")
+                            .from(ChatRoomMembers::Table, ChatRoomMembers::RoomId)
+                            .to(ChatRooms::Table, ChatRooms::RoomId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_chat_room_member_user")
+                            .from(ChatRoomMembers::Table, ChatRoomMembers::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_chat_room_members_room_user")
+                    .table(ChatRoomMembers::Table)
+                    .col(ChatRoomMembers::RoomId)
+                    .col(ChatRoomMembers::UserId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ChatRoomMembers::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ChatRoomMembers {
+    Table,
+    Id,
+    RoomId,
+    UserId,
+    Role,
+    FirstJoinedAt,
+    LastJoinedAt,
+}
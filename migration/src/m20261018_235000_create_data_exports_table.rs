@@ -0,0 +1,77 @@
+use sea_orm_migration::prelude::*;
+use crate::m20250514_154701_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DataExports::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DataExports::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(DataExports::UserId).integer().not_null())
+                    .col(ColumnDef::new(DataExports::RequestedBy).integer().not_null())
+                    .col(ColumnDef::new(DataExports::Status).string().not_null())
+                    .col(ColumnDef::new(DataExports::FileName).string().null())
+                    .col(ColumnDef::new(DataExports::Error).string().null())
+                    .col(
+                        ColumnDef::new(DataExports::CompletedAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(DataExports::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .null(),
+                    )
+                    .col(
+                        ColumnDef::new(DataExports::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_data_export_user")
+                            .from(DataExports::Table, DataExports::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DataExports::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum DataExports {
+    Table,
+    Id,
+    UserId,
+    RequestedBy,
+    Status,
+    FileName,
+    Error,
+    CompletedAt,
+    ExpiresAt,
+    CreatedAt,
+}